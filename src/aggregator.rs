@@ -0,0 +1,770 @@
+use std::collections::{BTreeMap, HashSet};
+use std::{error, fmt};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use {Message, Metric, GaugeOp, Tag, Tags, DDSketch, HyperLogLog};
+
+/// A source of the current time as a Unix timestamp in seconds,
+/// closures returning a timestamp can be used as a clock
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+/// The system clock
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9)
+            .unwrap_or(0.0)
+    }
+}
+
+impl<F: Fn() -> f64> Clock for F {
+    fn now(&self) -> f64 {
+        self()
+    }
+}
+
+/// Options for the aggregator
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AggregatorOptions {
+    /// Seconds between flushes, see `flush_if_due`
+    pub flush_interval: f64,
+    /// Percentiles of timings and histograms, between 0 and 100,
+    /// like Etsy's `percentThreshold`. Negative percentiles are calculated over
+    /// the highest values, e.g. `-10` for the top 10%.
+    pub percentiles: Vec<f64>,
+    /// Relative accuracy of the sketches of distributions, between 0 and 1
+    pub relative_accuracy: f64,
+    /// Precision of the HyperLogLog sketches of sets, between 4 and 18.
+    /// Sets are only counted exactly when this is not set.
+    pub set_precision: Option<u8>,
+    /// Number of unique values after which a set switches from
+    /// exact to approximate counting with a HyperLogLog sketch
+    pub set_exact_threshold: usize,
+}
+
+impl Default for AggregatorOptions {
+    fn default() -> AggregatorOptions {
+        AggregatorOptions {
+            flush_interval: 10.0,
+            percentiles: vec![90.0],
+            relative_accuracy: 0.01,
+            set_precision: None,
+            set_exact_threshold: 1000,
+        }
+    }
+}
+
+impl AggregatorOptions {
+    /// Checks that the options are within their ranges
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if !self.flush_interval.is_finite() || self.flush_interval <= 0.0 {
+            return Err(OptionsError::InvalidFlushInterval)
+        }
+        for percentile in &self.percentiles {
+            if !percentile.is_finite() || *percentile == 0.0 || percentile.abs() > 100.0 {
+                return Err(OptionsError::InvalidPercentile)
+            }
+        }
+        if !DDSketch::is_valid_relative_accuracy(self.relative_accuracy) {
+            return Err(OptionsError::InvalidRelativeAccuracy)
+        }
+        match self.set_precision {
+            Some(precision) if !HyperLogLog::is_valid_precision(precision) => Err(OptionsError::InvalidSetPrecision),
+            _ => Ok(())
+        }
+    }
+}
+
+/// An aggregator option that is out of range
+#[derive(Debug,Clone,PartialEq)]
+pub enum OptionsError {
+    /// Flush interval is not greater than 0
+    InvalidFlushInterval,
+    /// Percentile is 0 or not between -100 and 100
+    InvalidPercentile,
+    /// Relative accuracy is not between 0 and 1
+    InvalidRelativeAccuracy,
+    /// Set precision is not between 4 and 18
+    InvalidSetPrecision,
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptionsError::InvalidFlushInterval => write!(f, "Flush interval is not greater than 0"),
+            OptionsError::InvalidPercentile => write!(f, "Percentile is 0 or not between -100 and 100"),
+            OptionsError::InvalidRelativeAccuracy => write!(f, "Relative accuracy is not between 0 and 1"),
+            OptionsError::InvalidSetPrecision => write!(f, "Set precision is not between 4 and 18")
+        }
+    }
+}
+
+impl error::Error for OptionsError {}
+
+/// Statistics of the values of a timing or histogram,
+/// the count, sum and mean are weighted by the sample rate
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Statistics {
+    pub count: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub sum: f64,
+    pub percentiles: Vec<Percentile>,
+}
+
+/// Statistics of the lowest values up to a percentile, or of the
+/// highest values for negative percentiles
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Percentile {
+    /// The percentile, between -100 and 100
+    pub threshold: f64,
+    /// The number of values within the threshold
+    pub count: f64,
+    /// The highest value within the threshold, or the lowest for negative percentiles
+    pub boundary: f64,
+    pub mean: f64,
+    pub sum: f64,
+}
+
+impl Percentile {
+    /// The statistics with the names Etsy's statsd uses, e.g.
+    /// `count_90`, `mean_90`, `upper_90` and `sum_90` for the 90th
+    /// percentile, `upper_99_9` for 99.9 and `lower_top10` for -10
+    pub fn fields(&self) -> Vec<(String, f64)> {
+        let suffix = self.threshold.to_string().replace('.', "_").replace('-', "top");
+        let boundary = if self.threshold > 0.0 { "upper" } else { "lower" };
+        vec![
+            (format!("count_{}", suffix), self.count),
+            (format!("mean_{}", suffix), self.mean),
+            (format!("{}_{}", boundary, suffix), self.boundary),
+            (format!("sum_{}", suffix), self.sum),
+        ]
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum AggregateValue {
+    /// The sum of the values scaled by the sample rate and the sum per second
+    Counter { count: f64, rate: f64 },
+    Gauge { value: f64 },
+    Timing(Statistics),
+    Histogram(Statistics),
+    /// A sketch that can be merged with the sketches of other
+    /// hosts and answers quantile queries
+    Distribution(DDSketch),
+    /// The number of unique values, with a sketch that can be merged with
+    /// the sketches of other hosts when a set precision is configured
+    Set { unique: usize, sketch: Option<HyperLogLog> }
+}
+
+/// The aggregated value of a metric with a name and tags
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aggregate {
+    pub name: String,
+    /// Tags sorted by key and value
+    pub tags: Tags,
+    pub value: AggregateValue,
+}
+
+/// The aggregates of a flush interval
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Flush {
+    /// Unix timestamp in seconds of the flush
+    pub timestamp: f64,
+    /// Seconds since the previous flush
+    pub interval: f64,
+    pub aggregates: Vec<Aggregate>,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+enum Kind {
+    Counter,
+    Gauge,
+    Timing,
+    Histogram,
+    Distribution,
+    Set
+}
+
+/// Series are identified by their name, tags and type
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord)]
+struct Key {
+    name: String,
+    tags: Vec<Tag>,
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum State {
+    Counter(f64),
+    Gauge(f64),
+    /// Values with their weight, one divided by the sample rate
+    Values(Vec<(f64, f64)>),
+    Sketch(DDSketch),
+    Set(HashSet<String>),
+    /// A set that has more unique values than the exact threshold
+    ApproximateSet(HyperLogLog)
+}
+
+/// Aggregates messages with StatsD semantics and flushes the results per interval.
+///
+/// Counters and meters are summed and scaled by their sample rate, gauges keep the
+/// last value and can be incremented and decremented, timings and histograms get
+/// statistics over their values, distributions are aggregated into a DDSketch and
+/// sets count the unique values.
+/// Gauges are kept between flushes, the other metrics are reset.
+#[derive(Debug)]
+pub struct Aggregator<C: Clock = SystemClock> {
+    options: AggregatorOptions,
+    clock: C,
+    last_flush: f64,
+    series: BTreeMap<Key, State>,
+}
+
+impl Aggregator<SystemClock> {
+    /// Creates an aggregator that uses the system clock,
+    /// returns an error when an option is out of range
+    pub fn new(options: AggregatorOptions) -> Result<Aggregator<SystemClock>, OptionsError> {
+        Aggregator::with_clock(options, SystemClock)
+    }
+}
+
+impl<C: Clock> Aggregator<C> {
+    /// Creates an aggregator that uses the given clock, the first interval starts
+    /// at the current time of the clock. Returns an error when an option is out of range.
+    pub fn with_clock(options: AggregatorOptions, clock: C) -> Result<Aggregator<C>, OptionsError> {
+        options.validate()?;
+        let last_flush = clock.now();
+        Ok(Aggregator {
+            options,
+            clock,
+            last_flush,
+            series: BTreeMap::new(),
+        })
+    }
+
+    /// Adds a message to the current interval and returns if it was
+    /// recorded, service checks and events are not aggregated
+    pub fn record(&mut self, message: &Message) -> bool {
+        let kind = match message.metric {
+            Metric::Counter(_) | Metric::Meter(_) => Kind::Counter,
+            Metric::Gauge(_) => Kind::Gauge,
+            Metric::Timing(_) => Kind::Timing,
+            Metric::Histogram(_) => Kind::Histogram,
+            Metric::Distribution(_) => Kind::Distribution,
+            Metric::Set(_) => Kind::Set,
+            Metric::ServiceCheck(_) | Metric::Event(_) => return false
+        };
+
+        let mut tags: Vec<Tag> = match message.tags {
+            Some(ref tags) => tags.iter().cloned().collect(),
+            None => Vec::new()
+        };
+        tags.sort();
+        let key = Key {
+            name: message.name.clone(),
+            tags,
+            kind,
+        };
+
+        let weight = message.metric.weight();
+
+        let relative_accuracy = self.options.relative_accuracy;
+        let state = self.series.entry(key).or_insert_with(|| {
+            match kind {
+                Kind::Counter => State::Counter(0.0),
+                Kind::Gauge => State::Gauge(0.0),
+                Kind::Timing | Kind::Histogram => State::Values(Vec::new()),
+                Kind::Distribution => State::Sketch(DDSketch::new(relative_accuracy)),
+                Kind::Set => State::Set(HashSet::new())
+            }
+        });
+
+        let mut approximate = None;
+        match (&mut *state, &message.metric) {
+            (State::Counter(count), Metric::Counter(counter)) => *count += counter.value * weight,
+            (State::Counter(count), Metric::Meter(meter)) => *count += meter.value * weight,
+            (State::Gauge(value), Metric::Gauge(gauge)) => {
+                match gauge.op {
+                    GaugeOp::SET => *value = gauge.value,
+                    GaugeOp::INCREMENT => *value += gauge.value,
+                    GaugeOp::DECREMENT => *value -= gauge.value
+                }
+            },
+            (State::Values(values), Metric::Timing(timing)) => {
+                values.extend(timing.values.iter().map(|value| (*value, weight)));
+            },
+            (State::Values(values), Metric::Histogram(histogram)) => {
+                values.extend(histogram.values.iter().map(|value| (*value, weight)));
+            },
+            (State::Sketch(sketch), Metric::Distribution(distribution)) => {
+                for value in &distribution.values {
+                    sketch.add_weighted(*value, weight);
+                }
+            },
+            (State::Set(values), Metric::Set(set)) => {
+                values.insert(set.value.clone());
+
+                // Switch to approximate counting to limit the memory that is used
+                if let Some(precision) = self.options.set_precision {
+                    if values.len() > self.options.set_exact_threshold {
+                        let mut sketch = HyperLogLog::new(precision);
+                        for value in values.iter() {
+                            sketch.add(value);
+                        }
+                        approximate = Some(sketch);
+                    }
+                }
+            },
+            (State::ApproximateSet(sketch), Metric::Set(set)) => {
+                sketch.add(&set.value);
+            },
+            _ => return false
+        }
+        if let Some(sketch) = approximate {
+            *state = State::ApproximateSet(sketch);
+        }
+        true
+    }
+
+    /// Unix timestamp in seconds of the next flush
+    pub fn next_flush(&self) -> f64 {
+        self.last_flush + self.options.flush_interval
+    }
+
+    /// Flushes when the flush interval has passed since the previous flush
+    pub fn flush_if_due(&mut self) -> Option<Flush> {
+        if self.clock.now() >= self.next_flush() {
+            Some(self.flush())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the aggregates since the previous flush and starts a new interval
+    pub fn flush(&mut self) -> Flush {
+        let timestamp = self.clock.now();
+        let interval = timestamp - self.last_flush;
+        self.last_flush = timestamp;
+
+        let series = ::std::mem::take(&mut self.series);
+        let mut aggregates = Vec::with_capacity(series.len());
+
+        for (key, state) in series {
+            let value = match state {
+                State::Counter(count) => {
+                    let rate = if interval > 0.0 { count / interval } else { 0.0 };
+                    AggregateValue::Counter { count, rate }
+                },
+                State::Gauge(value) => AggregateValue::Gauge { value },
+                State::Values(ref values) if values.is_empty() => continue,
+                State::Values(values) => {
+                    let statistics = self.statistics(values);
+                    match key.kind {
+                        Kind::Timing => AggregateValue::Timing(statistics),
+                        _ => AggregateValue::Histogram(statistics)
+                    }
+                },
+                State::Sketch(sketch) => AggregateValue::Distribution(sketch),
+                State::Set(values) => {
+                    let sketch = self.options.set_precision.map(|precision| {
+                        let mut sketch = HyperLogLog::new(precision);
+                        for value in &values {
+                            sketch.add(value);
+                        }
+                        sketch
+                    });
+                    AggregateValue::Set { unique: values.len(), sketch }
+                },
+                State::ApproximateSet(sketch) => AggregateValue::Set { unique: sketch.count(), sketch: Some(sketch) }
+            };
+
+            // Gauges are kept for the next interval
+            if let AggregateValue::Gauge { value } = value {
+                self.series.insert(key.clone(), State::Gauge(value));
+            }
+
+            aggregates.push(Aggregate {
+                name: key.name,
+                tags: key.tags.into_iter().collect(),
+                value,
+            });
+        }
+
+        Flush {
+            timestamp,
+            interval,
+            aggregates,
+        }
+    }
+
+    fn statistics(&self, mut values: Vec<(f64, f64)>) -> Statistics {
+        values.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+        let count: f64 = values.iter().map(|&(_, weight)| weight).sum();
+        let sum: f64 = values.iter().map(|&(value, weight)| value * weight).sum();
+        let percentiles = self.options.percentiles.iter()
+            .filter_map(|threshold| percentile(&values, count, *threshold))
+            .collect();
+
+        Statistics {
+            count,
+            min: values[0].0,
+            max: values[values.len() - 1].0,
+            mean: sum / count,
+            sum,
+            percentiles,
+        }
+    }
+}
+
+/// Calculates a percentile over the sorted values the way Etsy's statsd does, the number
+/// of values within the threshold is rounded to the nearest number. Every value counts as
+/// its weight, so sampled values take up more of the threshold. Returns None when no
+/// values are within the threshold.
+fn percentile(values: &[(f64, f64)], count: f64, threshold: f64) -> Option<Percentile> {
+    // A single value is within every threshold
+    let in_threshold = if values.len() > 1 {
+        (threshold.abs() / 100.0 * count).round()
+    } else {
+        count
+    };
+    if in_threshold <= 0.0 {
+        return None
+    }
+
+    let mut taken = 0.0;
+    let mut sum = 0.0;
+    let mut boundary = values[0].0;
+    let ordered: Box<dyn Iterator<Item = &(f64, f64)>> = if threshold > 0.0 {
+        Box::new(values.iter())
+    } else {
+        Box::new(values.iter().rev())
+    };
+    for &(value, weight) in ordered {
+        if taken >= in_threshold {
+            break;
+        }
+        // Only take the part of the weight that fits in the threshold
+        let take = weight.min(in_threshold - taken);
+        taken += take;
+        sum += value * take;
+        boundary = value;
+    }
+
+    Some(Percentile {
+        threshold,
+        count: in_threshold,
+        boundary,
+        mean: sum / in_threshold,
+        sum,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use {parse, Tags, DDSketch};
+    use super::{Aggregator, AggregatorOptions, OptionsError, Aggregate, AggregateValue, Statistics, Percentile};
+
+    fn aggregator(now: &Rc<Cell<f64>>, lines: &[&str]) -> Aggregator<impl Fn() -> f64> {
+        aggregator_with_options(now, AggregatorOptions::default(), lines)
+    }
+
+    fn aggregator_with_options(now: &Rc<Cell<f64>>, options: AggregatorOptions, lines: &[&str]) -> Aggregator<impl Fn() -> f64> {
+        let clock = now.clone();
+        let mut aggregator = Aggregator::with_clock(options, move || clock.get()).unwrap();
+        for line in lines {
+            assert!(aggregator.record(&parse(*line).unwrap()), "{}", line);
+        }
+        aggregator
+    }
+
+    fn aggregate(name: &str, value: AggregateValue) -> Aggregate {
+        Aggregate {
+            name: name.to_string(),
+            tags: Tags::new(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_counter() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &[
+            "gorets:1|c",
+            "gorets:2|c|@0.5",
+            "gorets:1|m",
+        ]);
+        now.set(1656581410.0);
+
+        let flush = aggregator.flush();
+        assert_eq!(flush.timestamp, 1656581410.0);
+        assert_eq!(flush.interval, 10.0);
+        assert_eq!(flush.aggregates, vec![aggregate("gorets", AggregateValue::Counter { count: 6.0, rate: 0.6 })]);
+
+        // Counters are reset after a flush
+        now.set(1656581420.0);
+        assert!(aggregator.flush().aggregates.is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_gauge() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &[
+            "gorets:+5|g",
+            "gorets:10|g",
+            "gorets:+5|g",
+            "gorets:-3|g",
+        ]);
+
+        assert_eq!(aggregator.flush().aggregates, vec![aggregate("gorets", AggregateValue::Gauge { value: 12.0 })]);
+
+        // Gauges are kept between flushes
+        aggregator.record(&parse("gorets:-2|g").unwrap());
+        assert_eq!(aggregator.flush().aggregates, vec![aggregate("gorets", AggregateValue::Gauge { value: 10.0 })]);
+        assert_eq!(aggregator.flush().aggregates, vec![aggregate("gorets", AggregateValue::Gauge { value: 10.0 })]);
+    }
+
+    #[test]
+    fn test_aggregate_timing() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &[
+            "latency:1:2:3:4:5|ms",
+            "latency:6:7:8:9:10|ms|@0.5",
+            "size:1|h",
+            "size:3|d",
+        ]);
+
+        let mut sketch = DDSketch::new(0.01);
+        sketch.add(3.0);
+
+        let expected = vec![
+            aggregate("latency", AggregateValue::Timing(Statistics {
+                count: 15.0,
+                min: 1.0,
+                max: 10.0,
+                mean: 95.0 / 15.0,
+                sum: 95.0,
+                percentiles: vec![Percentile { threshold: 90.0, count: 14.0, boundary: 10.0, mean: 85.0 / 14.0, sum: 85.0 }],
+            })),
+            aggregate("size", AggregateValue::Histogram(Statistics {
+                count: 1.0,
+                min: 1.0,
+                max: 1.0,
+                mean: 1.0,
+                sum: 1.0,
+                percentiles: vec![Percentile { threshold: 90.0, count: 1.0, boundary: 1.0, mean: 1.0, sum: 1.0 }],
+            })),
+            aggregate("size", AggregateValue::Distribution(sketch)),
+        ];
+
+        assert_eq!(aggregator.flush().aggregates, expected);
+    }
+
+    #[test]
+    fn test_aggregate_percentiles() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let options = AggregatorOptions { percentiles: vec![50.0, 90.0, 95.0, 99.0, 99.9, -10.0, 1.0], ..Default::default() };
+        let lines = ["latency:1:2:3:4:5:6:7:8:9:10|ms"];
+        let mut aggregator = aggregator_with_options(&now, options, &lines);
+
+        let percentiles = match aggregator.flush().aggregates[0].value {
+            AggregateValue::Timing(ref statistics) => statistics.percentiles.clone(),
+            _ => panic!("Expected a timing")
+        };
+
+        // Matches the percentThreshold of Etsy's statsd, the 1st
+        // percentile rounds to no values and is left out
+        assert_eq!(percentiles, vec![
+            Percentile { threshold: 50.0, count: 5.0, boundary: 5.0, mean: 3.0, sum: 15.0 },
+            Percentile { threshold: 90.0, count: 9.0, boundary: 9.0, mean: 5.0, sum: 45.0 },
+            Percentile { threshold: 95.0, count: 10.0, boundary: 10.0, mean: 5.5, sum: 55.0 },
+            Percentile { threshold: 99.0, count: 10.0, boundary: 10.0, mean: 5.5, sum: 55.0 },
+            Percentile { threshold: 99.9, count: 10.0, boundary: 10.0, mean: 5.5, sum: 55.0 },
+            Percentile { threshold: -10.0, count: 1.0, boundary: 10.0, mean: 10.0, sum: 10.0 },
+        ]);
+
+        let fields = percentiles[4].fields();
+        assert_eq!(fields, vec![
+            ("count_99_9".to_string(), 10.0),
+            ("mean_99_9".to_string(), 5.5),
+            ("upper_99_9".to_string(), 10.0),
+            ("sum_99_9".to_string(), 55.0),
+        ]);
+        assert_eq!(percentiles[5].fields()[2], ("lower_top10".to_string(), 10.0));
+    }
+
+    #[test]
+    fn test_aggregate_percentiles_sample_rate() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let options = AggregatorOptions { percentiles: vec![50.0], ..Default::default() };
+        let mut aggregator = aggregator_with_options(&now, options, &[
+            "latency:1:2:3|ms",
+            "latency:100|ms|@0.25",
+        ]);
+
+        // The sampled value counts as 4 values, so it is the median
+        let percentiles = match aggregator.flush().aggregates[0].value {
+            AggregateValue::Timing(ref statistics) => statistics.percentiles.clone(),
+            _ => panic!("Expected a timing")
+        };
+        assert_eq!(percentiles, vec![
+            Percentile { threshold: 50.0, count: 4.0, boundary: 100.0, mean: 106.0 / 4.0, sum: 106.0 },
+        ]);
+    }
+
+    #[test]
+    fn test_aggregate_distribution() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let options = AggregatorOptions { relative_accuracy: 0.02, ..Default::default() };
+        let mut aggregator = aggregator_with_options(&now, options, &[
+            "latency:10:20:30|d|#host:web1",
+            "latency:40|d|@0.5|#host:web1",
+            "latency:1000|d|#host:web2",
+        ]);
+
+        let flush = aggregator.flush();
+        let sketch = match flush.aggregates[0].value {
+            AggregateValue::Distribution(ref sketch) => sketch.clone(),
+            _ => panic!("Expected a distribution")
+        };
+        assert_eq!(sketch.count(), 5.0);
+        assert_eq!(sketch.sum(), 140.0);
+        assert!((sketch.quantile(0.5).unwrap() - 30.0).abs() <= 30.0 * 0.02);
+        assert_eq!(sketch.quantile(1.0), Some(40.0));
+
+        // Sketches of series can be merged
+        let mut merged = sketch.clone();
+        match flush.aggregates[1].value {
+            AggregateValue::Distribution(ref other) => assert!(merged.merge(other)),
+            _ => panic!("Expected a distribution")
+        }
+        assert_eq!(merged.count(), 6.0);
+        assert_eq!(merged.max(), Some(1000.0));
+    }
+
+    #[test]
+    fn test_aggregate_set() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &[
+            "users:alice|s",
+            "users:bob|s",
+            "users:alice|s",
+        ]);
+
+        assert_eq!(aggregator.flush().aggregates, vec![aggregate("users", AggregateValue::Set { unique: 2, sketch: None })]);
+    }
+
+    #[test]
+    fn test_aggregate_set_approximate() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let options = AggregatorOptions { set_precision: Some(12), set_exact_threshold: 100, ..Default::default() };
+        let mut aggregator = aggregator_with_options(&now, options, &[]);
+
+        // Counts exactly up to the threshold
+        for i in 0..100 {
+            aggregator.record(&parse(format!("users:user-{}|s", i)).unwrap());
+        }
+        let (unique, sketch) = match aggregator.flush().aggregates[0].value {
+            AggregateValue::Set { unique, ref sketch } => (unique, sketch.clone().unwrap()),
+            _ => panic!("Expected a set")
+        };
+        assert_eq!(unique, 100);
+        assert_eq!(sketch.precision(), 12);
+
+        // Estimates above the threshold
+        for i in 0..5000 {
+            aggregator.record(&parse(format!("users:user-{}|s", i)).unwrap());
+        }
+        let (unique, mut merged) = match aggregator.flush().aggregates[0].value {
+            AggregateValue::Set { unique, ref sketch } => (unique, sketch.clone().unwrap()),
+            _ => panic!("Expected a set")
+        };
+        assert!((unique as f64 - 5000.0).abs() / 5000.0 < 0.05, "counted {}", unique);
+
+        // The sketches can be merged
+        assert!(merged.merge(&sketch));
+        assert_eq!(merged.count(), unique);
+    }
+
+    #[test]
+    fn test_aggregate_tags() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &[
+            "gorets:1|c|#foo:bar,moo:maa",
+            "gorets:1|c|#moo:maa,foo:bar",
+            "gorets:1|c|#foo:baz",
+        ]);
+
+        let mut tags = Tags::new();
+        tags.push("foo", "bar");
+        tags.push("moo", "maa");
+
+        let flush = aggregator.flush();
+        assert_eq!(flush.aggregates.len(), 2);
+        assert_eq!(flush.aggregates[0].tags, tags);
+        assert_eq!(flush.aggregates[0].value, AggregateValue::Counter { count: 2.0, rate: 0.0 });
+    }
+
+    #[test]
+    fn test_aggregate_ignored() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &[]);
+
+        assert!(!aggregator.record(&parse("_sc|Redis connection|0").unwrap()));
+        assert!(!aggregator.record(&parse("_e{6,5}:deploy|v1|v2").unwrap()));
+    }
+
+    #[test]
+    fn test_flush_if_due() {
+        let now = Rc::new(Cell::new(1656581400.0));
+        let mut aggregator = aggregator(&now, &["gorets:1|c"]);
+        assert_eq!(aggregator.next_flush(), 1656581410.0);
+
+        now.set(1656581409.0);
+        assert_eq!(aggregator.flush_if_due(), None);
+
+        now.set(1656581410.0);
+        let flush = aggregator.flush_if_due().unwrap();
+        assert_eq!(flush.aggregates, vec![aggregate("gorets", AggregateValue::Counter { count: 1.0, rate: 0.1 })]);
+        assert_eq!(aggregator.next_flush(), 1656581420.0);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        for flush_interval in &[0.0, -10.0, f64::NAN, f64::INFINITY] {
+            let options = AggregatorOptions { flush_interval: *flush_interval, ..Default::default() };
+            assert_eq!(Aggregator::new(options).err(), Some(OptionsError::InvalidFlushInterval));
+        }
+        for percentile in &[0.0, 150.0, -100.5, f64::NAN, f64::INFINITY] {
+            let options = AggregatorOptions { percentiles: vec![90.0, *percentile], ..Default::default() };
+            assert_eq!(Aggregator::new(options).err(), Some(OptionsError::InvalidPercentile));
+        }
+        for relative_accuracy in &[0.0, 1.0, -0.5, f64::NAN] {
+            let options = AggregatorOptions { relative_accuracy: *relative_accuracy, ..Default::default() };
+            assert_eq!(Aggregator::new(options).err(), Some(OptionsError::InvalidRelativeAccuracy));
+        }
+        for set_precision in &[0, 3, 19, 20] {
+            let options = AggregatorOptions { set_precision: Some(*set_precision), ..Default::default() };
+            assert_eq!(Aggregator::new(options).err(), Some(OptionsError::InvalidSetPrecision));
+        }
+    }
+}
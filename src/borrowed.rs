@@ -0,0 +1,114 @@
+use {Message, Metric, TagRef, Cardinality, Status, Priority, AlertType};
+use {Gauge, Counter, Timing, Histogram, Meter, Distribution, Set, ServiceCheck, Event};
+
+/// A message that borrows its names, tags and other strings from the input
+#[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MessageRef<'a> {
+    pub name: &'a str,
+    pub tags: Option<Vec<TagRef<'a>>>,
+    pub timestamp: Option<f64>,
+    pub container_id: Option<&'a str>,
+    pub external_data: Option<&'a str>,
+    pub cardinality: Option<Cardinality>,
+    pub metric: MetricRef<'a>
+}
+
+#[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum MetricRef<'a> {
+    Gauge(Gauge),
+    Counter(Counter),
+    Timing(Timing),
+    Histogram(Histogram),
+    Meter(Meter),
+    Distribution(Distribution),
+    Set(SetRef<'a>),
+    ServiceCheck(ServiceCheckRef<'a>),
+    Event(EventRef<'a>)
+}
+
+#[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SetRef<'a> {
+    pub value: &'a str,
+    pub sample_rate: Option<f64>,
+}
+
+#[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ServiceCheckRef<'a> {
+    pub status: Status,
+    pub timestamp: Option<f64>,
+    pub hostname: Option<&'a str>,
+    pub message: Option<&'a str>,
+}
+
+#[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct EventRef<'a> {
+    pub text: &'a str,
+    pub timestamp: Option<f64>,
+    pub hostname: Option<&'a str>,
+    pub aggregation_key: Option<&'a str>,
+    pub priority: Option<Priority>,
+    pub source_type: Option<&'a str>,
+    pub alert_type: Option<AlertType>,
+}
+
+impl<'a> MessageRef<'a> {
+    /// Copies the borrowed strings into an owned message
+    pub fn to_owned(&self) -> Message {
+        Message {
+            name: self.name.to_owned(),
+            tags: self.tags.as_ref().map(|tags| {
+                tags.iter().map(|tag| tag.to_owned()).collect()
+            }),
+            timestamp: self.timestamp,
+            container_id: self.container_id.map(str::to_owned),
+            external_data: self.external_data.map(str::to_owned),
+            cardinality: self.cardinality.clone(),
+            metric: self.metric.to_owned()
+        }
+    }
+}
+
+impl<'a> MetricRef<'a> {
+    /// Copies the borrowed strings into an owned metric
+    pub fn to_owned(&self) -> Metric {
+        match *self {
+            MetricRef::Gauge(ref gauge) => Metric::Gauge(gauge.clone()),
+            MetricRef::Counter(ref counter) => Metric::Counter(counter.clone()),
+            MetricRef::Timing(ref timing) => Metric::Timing(timing.clone()),
+            MetricRef::Histogram(ref histogram) => Metric::Histogram(histogram.clone()),
+            MetricRef::Meter(ref meter) => Metric::Meter(meter.clone()),
+            MetricRef::Distribution(ref distribution) => Metric::Distribution(distribution.clone()),
+            MetricRef::Set(ref set) => {
+                Metric::Set(Set {
+                    value: set.value.to_owned(),
+                    sample_rate: set.sample_rate,
+                })
+            },
+            MetricRef::ServiceCheck(ref service_check) => {
+                Metric::ServiceCheck(ServiceCheck {
+                    status: service_check.status.clone(),
+                    timestamp: service_check.timestamp,
+                    hostname: service_check.hostname.map(str::to_owned),
+                    message: service_check.message.map(str::to_owned),
+                })
+            },
+            MetricRef::Event(ref event) => {
+                Metric::Event(Event {
+                    text: event.text.to_owned(),
+                    timestamp: event.timestamp,
+                    hostname: event.hostname.map(str::to_owned),
+                    aggregation_key: event.aggregation_key.map(str::to_owned),
+                    priority: event.priority.clone(),
+                    source_type: event.source_type.map(str::to_owned),
+                    alert_type: event.alert_type.clone(),
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use std::f64;
+
+/// A DDSketch, a quantile sketch with relative error guarantees that can be merged.
+///
+/// Values are counted in buckets with exponentially growing sizes, so quantiles
+/// are within the relative accuracy of the actual value. Sketches with the same
+/// relative accuracy can be merged, e.g. to combine distributions of multiple hosts.
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "DDSketchFields"))]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    /// Logarithm of the ratio between the bounds of a bucket,
+    /// this follows from the relative accuracy
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    gamma_ln: f64,
+    /// Counts of positive values by bucket index
+    positive: BTreeMap<i32, f64>,
+    /// Counts of negative values by the bucket index of their absolute value
+    negative: BTreeMap<i32, f64>,
+    /// Count of values that are too close to zero to index
+    zero_count: f64,
+    count: f64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+/// The fields of a deserialized sketch, the relative accuracy is checked
+/// and the bucket size is calculated from it
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct DDSketchFields {
+    relative_accuracy: f64,
+    positive: BTreeMap<i32, f64>,
+    negative: BTreeMap<i32, f64>,
+    zero_count: f64,
+    count: f64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+#[cfg(feature = "serde")]
+impl ::std::convert::TryFrom<DDSketchFields> for DDSketch {
+    type Error = String;
+
+    fn try_from(fields: DDSketchFields) -> Result<DDSketch, String> {
+        if !DDSketch::is_valid_relative_accuracy(fields.relative_accuracy) {
+            return Err(format!("relative accuracy {} is not between 0 and 1", fields.relative_accuracy))
+        }
+        Ok(DDSketch {
+            positive: fields.positive,
+            negative: fields.negative,
+            zero_count: fields.zero_count,
+            count: fields.count,
+            sum: fields.sum,
+            min: fields.min,
+            max: fields.max,
+            ..DDSketch::new(fields.relative_accuracy)
+        })
+    }
+}
+
+impl Default for DDSketch {
+    fn default() -> DDSketch {
+        DDSketch::new(0.01)
+    }
+}
+
+impl DDSketch {
+    /// Creates a sketch with a relative accuracy between 0 and 1, e.g.
+    /// `0.01` for quantiles that are within 1% of the actual value
+    pub fn new(relative_accuracy: f64) -> DDSketch {
+        assert!(DDSketch::is_valid_relative_accuracy(relative_accuracy), "relative accuracy must be between 0 and 1");
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        DDSketch {
+            relative_accuracy,
+            gamma_ln: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zero_count: 0.0,
+            count: 0.0,
+            sum: 0.0,
+            min: 0.0,
+            max: 0.0,
+        }
+    }
+
+    /// Returns if a sketch can be created with the relative accuracy
+    pub fn is_valid_relative_accuracy(relative_accuracy: f64) -> bool {
+        relative_accuracy > 0.0 && relative_accuracy < 1.0
+    }
+
+    pub fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.add_weighted(value, 1.0)
+    }
+
+    /// Adds a value that counts as the given weight, e.g. 1 divided by the sample rate
+    pub fn add_weighted(&mut self, value: f64, weight: f64) {
+        if !value.is_finite() || weight <= 0.0 {
+            return
+        }
+        if value >= f64::MIN_POSITIVE {
+            *self.positive.entry(self.index(value)).or_insert(0.0) += weight;
+        } else if value <= -f64::MIN_POSITIVE {
+            *self.negative.entry(self.index(-value)).or_insert(0.0) += weight;
+        } else {
+            self.zero_count += weight;
+        }
+        if self.is_empty() {
+            self.min = value;
+            self.max = value;
+        }
+        self.count += weight;
+        self.sum += value * weight;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Adds the values of another sketch, returns false and leaves the sketch
+    /// unchanged when the sketches have a different relative accuracy
+    pub fn merge(&mut self, other: &DDSketch) -> bool {
+        if self.gamma_ln != other.gamma_ln {
+            return false
+        }
+        if other.is_empty() {
+            return true
+        }
+        if self.is_empty() {
+            self.min = other.min;
+            self.max = other.max;
+        }
+        for (index, count) in &other.positive {
+            *self.positive.entry(*index).or_insert(0.0) += count;
+        }
+        for (index, count) in &other.negative {
+            *self.negative.entry(*index).or_insert(0.0) += count;
+        }
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        true
+    }
+
+    /// Returns the value at the quantile, between 0 and 1, or None for an empty
+    /// sketch. The value is within the relative accuracy, the minimum and maximum
+    /// are exact.
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.is_empty() || !(0.0..=1.0).contains(&quantile) {
+            return None
+        }
+        if quantile == 0.0 {
+            return Some(self.min)
+        }
+        if quantile == 1.0 {
+            return Some(self.max)
+        }
+        let rank = quantile * (self.count - 1.0);
+        let mut cumulative = 0.0;
+
+        // Negative values from the lowest, the highest index, up to zero
+        for (index, count) in self.negative.iter().rev() {
+            cumulative += count;
+            if cumulative > rank {
+                return Some(self.clamp(-self.value(*index)))
+            }
+        }
+        cumulative += self.zero_count;
+        if cumulative > rank {
+            return Some(self.clamp(0.0))
+        }
+        for (index, count) in &self.positive {
+            cumulative += count;
+            if cumulative > rank {
+                return Some(self.clamp(self.value(*index)))
+            }
+        }
+        Some(self.max)
+    }
+
+    /// The number of values, including their weight
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        if self.is_empty() { None } else { Some(self.min) }
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        if self.is_empty() { None } else { Some(self.max) }
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.is_empty() { None } else { Some(self.sum / self.count) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0.0
+    }
+
+    fn index(&self, value: f64) -> i32 {
+        (value.ln() / self.gamma_ln).ceil() as i32
+    }
+
+    /// The value in the middle of a bucket, it is within the
+    /// relative accuracy of all values in the bucket
+    fn value(&self, index: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (self.gamma_ln * f64::from(index)).exp() / (gamma + 1.0)
+    }
+
+    fn clamp(&self, value: f64) -> f64 {
+        value.max(self.min).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DDSketch;
+
+    fn assert_accurate(sketch: &DDSketch, quantile: f64, expected: f64) {
+        let value = sketch.quantile(quantile).unwrap();
+        let error = ((value - expected) / expected).abs();
+        assert!(error <= sketch.relative_accuracy(), "quantile {} is {}, expected {}", quantile, value, expected);
+    }
+
+    #[test]
+    fn test_ddsketch_quantile() {
+        let mut sketch = DDSketch::new(0.01);
+        for value in 1..1001 {
+            sketch.add(f64::from(value));
+        }
+
+        assert_eq!(sketch.count(), 1000.0);
+        assert_eq!(sketch.sum(), 500500.0);
+        assert_eq!(sketch.min(), Some(1.0));
+        assert_eq!(sketch.max(), Some(1000.0));
+        assert_eq!(sketch.quantile(0.0), Some(1.0));
+        assert_eq!(sketch.quantile(1.0), Some(1000.0));
+        assert_accurate(&sketch, 0.5, 500.0);
+        assert_accurate(&sketch, 0.9, 900.0);
+        assert_accurate(&sketch, 0.99, 990.0);
+        assert_eq!(sketch.quantile(1.5), None);
+    }
+
+    #[test]
+    fn test_ddsketch_empty() {
+        let sketch = DDSketch::default();
+
+        assert!(sketch.is_empty());
+        assert_eq!(sketch.quantile(0.5), None);
+        assert_eq!(sketch.min(), None);
+        assert_eq!(sketch.mean(), None);
+    }
+
+    #[test]
+    fn test_ddsketch_negative_and_zero() {
+        let mut sketch = DDSketch::new(0.02);
+        for value in &[-100.0, -10.0, 0.0, 0.0, 10.0] {
+            sketch.add(*value);
+        }
+
+        assert_eq!(sketch.quantile(0.0), Some(-100.0));
+        assert_accurate(&sketch, 0.25, -10.0);
+        assert_eq!(sketch.quantile(0.5), Some(0.0));
+        assert_eq!(sketch.quantile(1.0), Some(10.0));
+    }
+
+    #[test]
+    fn test_ddsketch_weighted() {
+        let mut sketch = DDSketch::new(0.01);
+        sketch.add(1.0);
+        sketch.add_weighted(100.0, 3.0);
+
+        assert_eq!(sketch.count(), 4.0);
+        assert_eq!(sketch.sum(), 301.0);
+        assert_accurate(&sketch, 0.5, 100.0);
+    }
+
+    #[test]
+    fn test_ddsketch_merge() {
+        let mut merged = DDSketch::new(0.01);
+        let mut other = DDSketch::new(0.01);
+        let mut expected = DDSketch::new(0.01);
+        for value in 1..501 {
+            merged.add(f64::from(value));
+            expected.add(f64::from(value));
+        }
+        for value in 501..1001 {
+            other.add(f64::from(value));
+            expected.add(f64::from(value));
+        }
+
+        assert!(merged.merge(&other));
+        assert_eq!(merged, expected);
+
+        // Sketches with another accuracy can not be merged
+        assert!(!merged.merge(&DDSketch::new(0.05)));
+        assert_eq!(merged, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ddsketch_serialize() {
+        let mut sketch = DDSketch::new(0.01);
+        for value in &[-1.5, 0.0, 2.0, 2.5, 1000.0] {
+            sketch.add(*value);
+        }
+
+        let json = ::serde_json::to_string(&sketch).unwrap();
+        assert!(!json.contains("gamma_ln"));
+        assert_eq!(::serde_json::from_str::<DDSketch>(&json).unwrap(), sketch);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ddsketch_deserialize_invalid() {
+        // The relative accuracy has to be between 0 and 1
+        let fields = r#""positive":{},"negative":{},"zero_count":0.0,"count":0.0,"sum":0.0,"min":0.0,"max":0.0"#;
+        assert!(::serde_json::from_str::<DDSketch>(&format!(r#"{{"relative_accuracy":5.0,{}}}"#, fields)).is_err());
+        assert!(::serde_json::from_str::<DDSketch>(&format!(r#"{{"relative_accuracy":0.0,{}}}"#, fields)).is_err());
+
+        // The bucket size follows from the relative accuracy
+        let sketch = ::serde_json::from_str::<DDSketch>(&format!(r#"{{"relative_accuracy":0.01,"gamma_ln":0.0,{}}}"#, fields)).unwrap();
+        assert_eq!(sketch, DDSketch::new(0.01));
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt;
+
+use {Message, Metric, Tags, GaugeOp, ServiceCheck, Event};
+
+/// Writes the message as a (Dog)StatsD line, parsing the result returns
+/// the same message for messages that come from the parser.
+///
+/// Gauges are written by their effect, an increment by a negative value is
+/// written as a decrement. A gauge that is set to a negative value can't be
+/// written as a single line, the sign makes it a decrement, use `encode` to
+/// get lines that set the gauge to the value.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sample_rate = match self.metric {
+            Metric::Gauge(ref gauge) => {
+                let sign = match gauge.op {
+                    GaugeOp::SET if gauge.value < 0.0 => "-",
+                    GaugeOp::SET => "",
+                    GaugeOp::INCREMENT if gauge.value < 0.0 => "-",
+                    GaugeOp::INCREMENT => "+",
+                    GaugeOp::DECREMENT if gauge.value < 0.0 => "+",
+                    GaugeOp::DECREMENT => "-"
+                };
+                write!(f, "{}:{}{}|g", self.name, sign, gauge.value.abs())?;
+                gauge.sample_rate
+            },
+            Metric::Counter(ref counter) => {
+                write!(f, "{}:{}|c", self.name, counter.value)?;
+                counter.sample_rate
+            },
+            Metric::Timing(ref timing) => {
+                write!(f, "{}:", self.name)?;
+                write_values(f, &timing.values)?;
+                write!(f, "|ms")?;
+                timing.sample_rate
+            },
+            Metric::Histogram(ref histogram) => {
+                write!(f, "{}:", self.name)?;
+                write_values(f, &histogram.values)?;
+                write!(f, "|h")?;
+                histogram.sample_rate
+            },
+            Metric::Meter(ref meter) => {
+                write!(f, "{}:{}|m", self.name, meter.value)?;
+                meter.sample_rate
+            },
+            Metric::Distribution(ref distribution) => {
+                write!(f, "{}:", self.name)?;
+                write_values(f, &distribution.values)?;
+                write!(f, "|d")?;
+                distribution.sample_rate
+            },
+            Metric::Set(ref set) => {
+                write!(f, "{}:{}|s", self.name, set.value)?;
+                set.sample_rate
+            },
+            Metric::ServiceCheck(ref service_check) => return write_service_check(f, self, service_check),
+            Metric::Event(ref event) => return write_event(f, self, event)
+        };
+
+        write_sections(f, self, sample_rate)
+    }
+}
+
+impl Message {
+    /// Writes the message as (Dog)StatsD lines. A gauge that is set to a negative
+    /// value is written as a line that sets the gauge to 0 and a line that decrements
+    /// it, every other message is written as a single line like `to_string`.
+    pub fn encode(&self) -> Vec<String> {
+        let line = self.to_string();
+        if let Metric::Gauge(ref gauge) = self.metric {
+            if gauge.op == GaugeOp::SET && gauge.value < 0.0 {
+                // The reset has the same sections as the decrement
+                let sections = &line[format!("{}:-{}|g", self.name, gauge.value.abs()).len()..];
+                return vec![format!("{}:0|g{}", self.name, sections), line]
+            }
+        }
+        vec![line]
+    }
+}
+
+/// Writes the optional sections of a metric
+fn write_sections(f: &mut fmt::Formatter, message: &Message, sample_rate: Option<f64>) -> fmt::Result {
+    if let Some(sample_rate) = sample_rate {
+        write!(f, "|@{}", sample_rate)?;
+    }
+    write_tags(f, &message.tags)?;
+    if let Some(timestamp) = message.timestamp {
+        write!(f, "|T{}", timestamp)?;
+    }
+    if let Some(ref container_id) = message.container_id {
+        write!(f, "|c:{}", container_id)?;
+    }
+    if let Some(ref external_data) = message.external_data {
+        write!(f, "|e:{}", external_data)?;
+    }
+    if let Some(ref cardinality) = message.cardinality {
+        write!(f, "|card:{}", cardinality.as_str())?;
+    }
+    Ok(())
+}
+
+fn write_service_check(f: &mut fmt::Formatter, message: &Message, service_check: &ServiceCheck) -> fmt::Result {
+    write!(f, "_sc|{}|{}", message.name, service_check.status.as_str())?;
+    if let Some(timestamp) = service_check.timestamp {
+        write!(f, "|d:{}", timestamp)?;
+    }
+    if let Some(ref hostname) = service_check.hostname {
+        write!(f, "|h:{}", hostname)?;
+    }
+    write_tags(f, &message.tags)?;
+    if let Some(ref message) = service_check.message {
+        write!(f, "|m:{}", message)?;
+    }
+    Ok(())
+}
+
+/// Writes the event, the header contains the lengths of the title and text in bytes
+fn write_event(f: &mut fmt::Formatter, message: &Message, event: &Event) -> fmt::Result {
+    write!(f, "_e{{{},{}}}:{}|{}", message.name.len(), event.text.len(), message.name, event.text)?;
+    if let Some(timestamp) = event.timestamp {
+        write!(f, "|d:{}", timestamp)?;
+    }
+    if let Some(ref hostname) = event.hostname {
+        write!(f, "|h:{}", hostname)?;
+    }
+    if let Some(ref aggregation_key) = event.aggregation_key {
+        write!(f, "|k:{}", aggregation_key)?;
+    }
+    if let Some(ref priority) = event.priority {
+        write!(f, "|p:{}", priority.as_str())?;
+    }
+    if let Some(ref source_type) = event.source_type {
+        write!(f, "|s:{}", source_type)?;
+    }
+    if let Some(ref alert_type) = event.alert_type {
+        write!(f, "|t:{}", alert_type.as_str())?;
+    }
+    write_tags(f, &message.tags)
+}
+
+/// Writes the values separated by a colon (`:`)
+fn write_values(f: &mut fmt::Formatter, values: &[f64]) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ":")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    Ok(())
+}
+
+/// Writes the tags section, tags without a value are written without a colon
+fn write_tags(f: &mut fmt::Formatter, tags: &Option<Tags>) -> fmt::Result {
+    if let Some(ref tags) = *tags {
+        write!(f, "|#")?;
+        for (i, tag) in tags.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match tag.value {
+                Some(ref value) => write!(f, "{}:{}", tag.key, value)?,
+                None => write!(f, "{}", tag.key)?
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {parse, Message, Metric, Counter, Gauge, GaugeOp, Event, Tags};
+
+    #[test]
+    fn test_display_metric() {
+        let mut tags = Tags::new();
+        tags.push("foo", "bar");
+        tags.push_key("moo");
+
+        let message = Message {
+            name: "gorets".to_string(),
+            tags: Some(tags),
+            timestamp: Some(1656581400f64),
+            container_id: Some("3ac6ec42".to_string()),
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Counter(Counter {
+                value: 1.0,
+                sample_rate: Some(0.5),
+            })
+        };
+
+        assert_eq!(message.to_string(), "gorets:1|c|@0.5|#foo:bar,moo|T1656581400|c:3ac6ec42");
+    }
+
+    #[test]
+    fn test_display_service_check() {
+        let message = parse("_sc|Redis connection|1|h:frontend1").unwrap();
+        assert_eq!(message.to_string(), "_sc|Redis connection|1|h:frontend1");
+    }
+
+    #[test]
+    fn test_display_event() {
+        let message = parse("_e{6,5}:deploy|v1|v2|t:info|#foo:bar").unwrap();
+        assert_eq!(message.to_string(), "_e{6,5}:deploy|v1|v2|t:info|#foo:bar");
+    }
+
+    #[test]
+    fn test_display_gauge() {
+        let gauge = |value, op| {
+            Message {
+                name: "gorets".to_string(),
+                tags: None,
+                timestamp: None,
+                container_id: None,
+                external_data: None,
+                cardinality: None,
+                metric: Metric::Gauge(Gauge { value, op, sample_rate: None })
+            }
+        };
+
+        // Gauges are written by their effect
+        assert_eq!(gauge(3.0, GaugeOp::INCREMENT).to_string(), "gorets:+3|g");
+        assert_eq!(gauge(-3.0, GaugeOp::INCREMENT).to_string(), "gorets:-3|g");
+        assert_eq!(gauge(-3.0, GaugeOp::DECREMENT).to_string(), "gorets:+3|g");
+
+        // A negative gauge can't be set in a single line
+        let mut message = gauge(-3.0, GaugeOp::SET);
+        message.tags = Some(Tags::new());
+        message.tags.as_mut().unwrap().push("foo", "bar");
+        assert_eq!(message.to_string(), "gorets:-3|g|#foo:bar");
+        assert_eq!(message.encode(), vec!["gorets:0|g|#foo:bar", "gorets:-3|g|#foo:bar"]);
+
+        let metrics: Vec<_> = message.encode().iter().map(|line| parse(line.as_str()).unwrap().metric).collect();
+        assert_eq!(metrics, vec![
+            Metric::Gauge(Gauge { value: 0.0, op: GaugeOp::SET, sample_rate: None }),
+            Metric::Gauge(Gauge { value: 3.0, op: GaugeOp::DECREMENT, sample_rate: None }),
+        ]);
+        assert_eq!(gauge(3.0, GaugeOp::SET).encode(), vec!["gorets:3|g"]);
+    }
+
+    #[test]
+    fn test_display_event_trailing_whitespace() {
+        let message = Message {
+            name: "deploy".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Event(Event {
+                text: "v1 ".to_string(),
+                timestamp: None,
+                hostname: None,
+                aggregation_key: None,
+                priority: None,
+                source_type: None,
+                alert_type: None,
+            })
+        };
+
+        assert_eq!(parse(message.to_string()), Ok(message));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let lines = vec![
+            "gorets:1|c",
+            "gorets:1.5|c|@0.9|#foo:bar,moo:maa",
+            "gorets:1|g",
+            "gorets:+5|g",
+            "gorets:-3|g|#foo",
+            "gorets:233|ms|T1656581400",
+            "gorets:233:12.5:7|h|@0.5",
+            "gorets:233|m",
+            "latency:12:15:9|d|#foo:,moo",
+            "users.unique:alice@example.com|s",
+            "gorets:1|c|c:3ac6ec42|e:it-false,cn-redis|card:orchestrator",
+            "goretsβ:1|c|#team:web,team:ops",
+            "_sc|Redis connection",
+            "_sc|Redis connection|2|d:10101|h:frontend1|#redis_instance:10.0.0.16:6379|m:Redis connection timed out after 10s",
+            "_e{6,5}:deploy|v1|v2",
+            "_e{21,36}:An exception occurred|Cannot parse CSV file from 10.0.0.17|d:1656581400|h:frontend1|k:csv|p:low|s:user|t:warning|#err_type:bad_file",
+            "_e{7,3}:titleβ|tξ|p:normal|t:success",
+        ];
+
+        for line in lines {
+            let message = parse(line).unwrap();
+            assert_eq!(parse(message.to_string()), Ok(message), "{}", line);
+        }
+    }
+}
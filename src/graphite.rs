@@ -0,0 +1,231 @@
+use {Message, Metric, GaugeOp};
+
+/// How tags are written to a Graphite path
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum GraphiteTags {
+    /// Graphite 1.1 tags, `name;key=value`
+    TAGGED,
+    /// Tags are flattened into the path, `name.key.value`
+    PATH,
+    /// Tags are left out
+    NONE
+}
+
+/// Options to convert messages to the Carbon plaintext protocol
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GraphiteOptions {
+    /// Prefix for every path, e.g. `stats`
+    pub prefix: Option<String>,
+    pub tags: GraphiteTags,
+    /// Replace whitespace with `_` and `/` with `-` and remove other
+    /// characters that are not allowed in names and tags
+    pub sanitize: bool,
+}
+
+impl Default for GraphiteOptions {
+    fn default() -> GraphiteOptions {
+        GraphiteOptions {
+            prefix: None,
+            tags: GraphiteTags::TAGGED,
+            sanitize: true,
+        }
+    }
+}
+
+impl GraphiteOptions {
+    /// Converts a message to Carbon plaintext lines (`path value timestamp`),
+    /// the given timestamp is used when the message has no timestamp.
+    ///
+    /// Counters and meters are scaled by their sample rate, so a sampled counter
+    /// gets the estimated total. Timings, histograms and distributions get a line
+    /// for every value and service checks are written with their status (0-3).
+    /// Sets and events have no numeric value and return no lines. Graphite stores the value of a gauge,
+    /// so gauges that are incremented or decremented return no lines, use an
+    /// `Aggregator` to get the value of these gauges.
+    pub fn format(&self, message: &Message, timestamp: f64) -> Vec<String> {
+        let (values, message_timestamp) = match message.metric {
+            Metric::Gauge(ref gauge) => {
+                if gauge.op != GaugeOp::SET {
+                    return Vec::new()
+                }
+                (vec![gauge.value], message.timestamp)
+            },
+            Metric::Counter(ref counter) => (vec![counter.value * message.metric.weight()], message.timestamp),
+            Metric::Timing(ref timing) => (timing.values.clone(), message.timestamp),
+            Metric::Histogram(ref histogram) => (histogram.values.clone(), message.timestamp),
+            Metric::Meter(ref meter) => (vec![meter.value * message.metric.weight()], message.timestamp),
+            Metric::Distribution(ref distribution) => (distribution.values.clone(), message.timestamp),
+            Metric::ServiceCheck(ref service_check) => {
+                (vec![f64::from(service_check.status.code())], service_check.timestamp)
+            },
+            Metric::Set(_) | Metric::Event(_) => return Vec::new()
+        };
+        let timestamp = message_timestamp.unwrap_or(timestamp);
+        let path = self.path(message);
+
+        values.iter()
+            .map(|value| format!("{} {} {}", path, value, timestamp.trunc()))
+            .collect()
+    }
+
+    fn path(&self, message: &Message) -> String {
+        let mut path = String::new();
+
+        if let Some(ref prefix) = self.prefix {
+            path.push_str(prefix);
+            if !prefix.ends_with('.') {
+                path.push('.');
+            }
+        }
+        path.push_str(&self.sanitize_name(&message.name));
+
+        let tags = match message.tags {
+            Some(ref tags) => tags,
+            None => return path
+        };
+        match self.tags {
+            GraphiteTags::TAGGED => {
+                // Graphite does not accept tags with an empty value
+                for tag in tags {
+                    let key = self.sanitize_tag(&tag.key);
+                    let value = match tag.value {
+                        Some(ref value) => self.sanitize_tag(value),
+                        None => String::new()
+                    };
+                    if key.is_empty() || value.is_empty() {
+                        continue;
+                    }
+                    path.push_str(&format!(";{}={}", key, value));
+                }
+            },
+            GraphiteTags::PATH => {
+                // Dots in tags would add extra nodes to the path
+                for tag in tags {
+                    path.push('.');
+                    path.push_str(&self.sanitize_name(&tag.key).replace('.', "_"));
+                    if let Some(ref value) = tag.value {
+                        if !value.is_empty() {
+                            path.push('.');
+                            path.push_str(&self.sanitize_name(value).replace('.', "_"));
+                        }
+                    }
+                }
+            },
+            GraphiteTags::NONE => ()
+        }
+        path
+    }
+
+    fn sanitize_name(&self, name: &str) -> String {
+        if !self.sanitize {
+            return name.to_owned()
+        }
+        let mut sanitized = String::with_capacity(name.len());
+        let mut whitespace = false;
+        for c in name.chars() {
+            if c.is_whitespace() {
+                // A run of whitespace becomes a single underscore
+                if !whitespace {
+                    sanitized.push('_');
+                }
+                whitespace = true;
+                continue;
+            }
+            whitespace = false;
+            match c {
+                '/' => sanitized.push('-'),
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' => sanitized.push(c),
+                _ => ()
+            }
+        }
+        sanitized
+    }
+
+    fn sanitize_tag(&self, tag: &str) -> String {
+        if !self.sanitize {
+            return tag.to_owned()
+        }
+        tag.chars()
+            .filter(|c| !";!^=~".contains(*c))
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parse;
+    use super::{GraphiteOptions, GraphiteTags};
+
+    fn format(options: &GraphiteOptions, input: &str) -> Vec<String> {
+        options.format(&parse(input).unwrap(), 1656581400.0)
+    }
+
+    #[test]
+    fn test_graphite_tagged() {
+        let options = GraphiteOptions::default();
+
+        assert_eq!(format(&options, "gorets:1|c"), vec!["gorets 1 1656581400"]);
+        assert_eq!(format(&options, "gorets:1.5|c|#foo:bar,moo,env:"), vec!["gorets;foo=bar 1.5 1656581400"]);
+        assert_eq!(format(&options, "gorets:1|c|#redis_instance:10.0.0.16:6379"), vec!["gorets;redis_instance=10.0.0.16:6379 1 1656581400"]);
+        assert_eq!(format(&options, "gorets:1|c|#foo:b;a=r"), vec!["gorets;foo=bar 1 1656581400"]);
+    }
+
+    #[test]
+    fn test_graphite_path() {
+        let options = GraphiteOptions { tags: GraphiteTags::PATH, ..Default::default() };
+
+        assert_eq!(format(&options, "gorets:1|c|#foo:bar,moo,env:"), vec!["gorets.foo.bar.moo.env 1 1656581400"]);
+        assert_eq!(format(&options, "gorets:1|c|#host:web.local"), vec!["gorets.host.web_local 1 1656581400"]);
+
+        let options = GraphiteOptions { tags: GraphiteTags::NONE, ..Default::default() };
+        assert_eq!(format(&options, "gorets:1|c|#foo:bar"), vec!["gorets 1 1656581400"]);
+    }
+
+    #[test]
+    fn test_graphite_sanitize() {
+        let options = GraphiteOptions::default();
+        assert_eq!(format(&options, "api/users  list!β:1|c"), vec!["api-users_list 1 1656581400"]);
+
+        let options = GraphiteOptions { sanitize: false, ..Default::default() };
+        assert_eq!(format(&options, "api/users:1|c"), vec!["api/users 1 1656581400"]);
+    }
+
+    #[test]
+    fn test_graphite_prefix() {
+        let options = GraphiteOptions { prefix: Some("stats".to_string()), ..Default::default() };
+        assert_eq!(format(&options, "gorets:1|c"), vec!["stats.gorets 1 1656581400"]);
+
+        let options = GraphiteOptions { prefix: Some("stats.".to_string()), ..Default::default() };
+        assert_eq!(format(&options, "gorets:1|c"), vec!["stats.gorets 1 1656581400"]);
+    }
+
+    #[test]
+    fn test_graphite_metrics() {
+        let options = GraphiteOptions::default();
+
+        // Only gauges that are set have a value
+        assert_eq!(format(&options, "gorets:5|g"), vec!["gorets 5 1656581400"]);
+        assert!(format(&options, "gorets:+5|g").is_empty());
+        assert!(format(&options, "gorets:-3|g").is_empty());
+
+        // Counters and meters are scaled by their sample rate
+        assert_eq!(format(&options, "gorets:1|c|@0.1"), vec!["gorets 10 1656581400"]);
+        assert_eq!(format(&options, "gorets:2|m|@0.5"), vec!["gorets 4 1656581400"]);
+
+        // Every value gets a line
+        assert_eq!(format(&options, "gorets:233:12.5|ms"), vec!["gorets 233 1656581400", "gorets 12.5 1656581400"]);
+
+        // The timestamp of the message is used
+        assert_eq!(format(&options, "gorets:233|h|T1656581500"), vec!["gorets 233 1656581500"]);
+        assert_eq!(format(&options, "_sc|Redis connection|2|d:1656581500"), vec!["Redis_connection 2 1656581500"]);
+
+        // Sets and events have no numeric value
+        assert!(format(&options, "users.unique:alice|s").is_empty());
+        assert!(format(&options, "_e{6,5}:deploy|v1|v2").is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+/// A HyperLogLog, estimates the number of unique values in a fixed amount of memory.
+///
+/// The precision is the number of bits used to pick a register, a sketch uses
+/// 2^precision bytes and has a standard error of about 1.04 / sqrt(2^precision).
+/// Sketches with the same precision can be merged, e.g. to count the unique
+/// values of multiple hosts.
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "HyperLogLogFields"))]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+/// The fields of a deserialized sketch, these are checked
+/// so a sketch always has a register for every index
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct HyperLogLogFields {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl ::std::convert::TryFrom<HyperLogLogFields> for HyperLogLog {
+    type Error = String;
+
+    fn try_from(fields: HyperLogLogFields) -> Result<HyperLogLog, String> {
+        if !HyperLogLog::is_valid_precision(fields.precision) {
+            return Err(format!("precision {} is not between 4 and 18", fields.precision))
+        }
+        if fields.registers.len() != 1 << fields.precision {
+            return Err(format!("expected {} registers for precision {}, got {}", 1 << fields.precision, fields.precision, fields.registers.len()))
+        }
+        Ok(HyperLogLog {
+            precision: fields.precision,
+            registers: fields.registers,
+        })
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> HyperLogLog {
+        HyperLogLog::new(14)
+    }
+}
+
+impl HyperLogLog {
+    /// Creates a sketch with a precision between 4 and 18
+    pub fn new(precision: u8) -> HyperLogLog {
+        assert!(HyperLogLog::is_valid_precision(precision), "precision must be between 4 and 18");
+        HyperLogLog {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Returns if a sketch can be created with the precision
+    pub fn is_valid_precision(precision: u8) -> bool {
+        (4..=18).contains(&precision)
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn add(&mut self, value: &str) {
+        let hash = hash(value.as_bytes());
+        let index = (hash >> (64 - self.precision)) as usize;
+        // The position of the first set bit in the remaining bits, the
+        // extra bit limits the rank when the remaining bits are all zero
+        let remaining = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = remaining.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Adds the values of another sketch, returns false and leaves the sketch
+    /// unchanged when the sketches have a different precision
+    pub fn merge(&mut self, other: &HyperLogLog) -> bool {
+        if self.precision != other.precision {
+            return false
+        }
+        for (register, other) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if *other > *register {
+                *register = *other;
+            }
+        }
+        true
+    }
+
+    /// The estimated number of unique values
+    pub fn count(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m)
+        };
+        let sum: f64 = self.registers.iter().map(|register| 2f64.powi(-i32::from(*register))).sum();
+        let estimate = alpha * m * m / sum;
+
+        // Linear counting is more accurate for small numbers of values
+        let zeros = self.registers.iter().filter(|register| **register == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.iter().all(|register| *register == 0)
+    }
+}
+
+/// A 64 bit FNV-1a hash with the MurmurHash3 finalizer to mix the bits,
+/// the hash is stable so sketches of different hosts can be merged
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HyperLogLog;
+
+    fn assert_accurate(sketch: &HyperLogLog, expected: usize) {
+        let error = (sketch.count() as f64 - expected as f64).abs() / expected as f64;
+        assert!(error < 0.03, "counted {}, expected {}", sketch.count(), expected);
+    }
+
+    #[test]
+    fn test_hyperloglog_count() {
+        let mut sketch = HyperLogLog::new(14);
+        assert!(sketch.is_empty());
+        assert_eq!(sketch.count(), 0);
+
+        for i in 0..100000 {
+            sketch.add(&format!("user-{}", i));
+        }
+        assert_accurate(&sketch, 100000);
+
+        // Adding the same values does not change the count
+        let count = sketch.count();
+        for i in 0..1000 {
+            sketch.add(&format!("user-{}", i));
+        }
+        assert_eq!(sketch.count(), count);
+    }
+
+    #[test]
+    fn test_hyperloglog_small() {
+        let mut sketch = HyperLogLog::new(12);
+        for i in 0..10 {
+            sketch.add(&format!("user-{}", i));
+        }
+        assert_eq!(sketch.count(), 10);
+    }
+
+    #[test]
+    fn test_hyperloglog_merge() {
+        let mut merged = HyperLogLog::new(12);
+        let mut other = HyperLogLog::new(12);
+        let mut expected = HyperLogLog::new(12);
+        for i in 0..5000 {
+            merged.add(&format!("user-{}", i));
+            expected.add(&format!("user-{}", i));
+        }
+        for i in 2500..10000 {
+            other.add(&format!("user-{}", i));
+            expected.add(&format!("user-{}", i));
+        }
+
+        assert!(merged.merge(&other));
+        assert_eq!(merged, expected);
+        assert!((merged.count() as f64 - 10000.0).abs() / 10000.0 < 0.05);
+
+        // Sketches with another precision can not be merged
+        assert!(!merged.merge(&HyperLogLog::new(14)));
+        assert_eq!(merged, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_hyperloglog_serialize() {
+        let mut sketch = HyperLogLog::new(4);
+        sketch.add("alice");
+        sketch.add("bob");
+
+        let json = ::serde_json::to_string(&sketch).unwrap();
+        assert_eq!(::serde_json::from_str::<HyperLogLog>(&json).unwrap(), sketch);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_hyperloglog_deserialize_invalid() {
+        // A sketch needs a register for every index
+        assert!(::serde_json::from_str::<HyperLogLog>(r#"{"precision":14,"registers":[0]}"#).is_err());
+        assert!(::serde_json::from_str::<HyperLogLog>(r#"{"precision":2,"registers":[0,0,0,0]}"#).is_err());
+    }
+}
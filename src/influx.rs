@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+
+use {Message, Metric, GaugeOp};
+
+/// Precision of the timestamps written to InfluxDB
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum InfluxPrecision {
+    SECONDS,
+    MILLISECONDS,
+    MICROSECONDS,
+    NANOSECONDS
+}
+
+/// Options to convert messages to the InfluxDB line protocol
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct InfluxOptions {
+    /// Precision of the timestamps, this has to match the
+    /// precision that is used to write to InfluxDB
+    pub precision: InfluxPrecision,
+}
+
+impl Default for InfluxOptions {
+    fn default() -> InfluxOptions {
+        InfluxOptions {
+            precision: InfluxPrecision::NANOSECONDS,
+        }
+    }
+}
+
+/// A field value, strings are quoted and integers get an `i` suffix
+enum FieldValue<'a> {
+    Float(f64),
+    Integer(i64),
+    String(&'a str)
+}
+
+impl InfluxOptions {
+    /// Converts a message to InfluxDB lines (`measurement,tags fields timestamp`).
+    /// The timestamp of the message is used, or the given timestamp in seconds when
+    /// the message has none. Without a timestamp InfluxDB uses the time it received
+    /// the line.
+    ///
+    /// Gauges and timings, histograms and distributions get a `value` field, counters and
+    /// meters a `count` field and the sample rate is added as a `sample_rate` field.
+    /// InfluxDB stores the value of a gauge, so gauges that are incremented or
+    /// decremented return no lines, use an `Aggregator` to get the value of these gauges.
+    /// Timings, histograms and distributions get a line for every value. Sets get a
+    /// string `value` field, service checks a `status` field (0-3) and events a `text`
+    /// field. Tags without a value are left out, InfluxDB does not accept empty tag values,
+    /// and tags are sorted by key with the last value for keys that occur more than once.
+    /// InfluxDB does not accept NaN and infinite values, these fields are left out and
+    /// lines without fields are skipped.
+    pub fn format(&self, message: &Message, timestamp: Option<f64>) -> Vec<String> {
+        let mut lines: Vec<Vec<(&str, FieldValue)>> = Vec::new();
+        let mut sample_rate = None;
+        let mut message_timestamp = message.timestamp;
+
+        match message.metric {
+            Metric::Gauge(ref gauge) => {
+                if gauge.op != GaugeOp::SET {
+                    return Vec::new()
+                }
+                lines.push(vec![("value", FieldValue::Float(gauge.value))]);
+                sample_rate = gauge.sample_rate;
+            },
+            Metric::Counter(ref counter) => {
+                lines.push(vec![("count", FieldValue::Float(counter.value))]);
+                sample_rate = counter.sample_rate;
+            },
+            Metric::Meter(ref meter) => {
+                lines.push(vec![("count", FieldValue::Float(meter.value))]);
+                sample_rate = meter.sample_rate;
+            },
+            Metric::Timing(ref timing) => {
+                lines.extend(timing.values.iter().map(|value| vec![("value", FieldValue::Float(*value))]));
+                sample_rate = timing.sample_rate;
+            },
+            Metric::Histogram(ref histogram) => {
+                lines.extend(histogram.values.iter().map(|value| vec![("value", FieldValue::Float(*value))]));
+                sample_rate = histogram.sample_rate;
+            },
+            Metric::Distribution(ref distribution) => {
+                lines.extend(distribution.values.iter().map(|value| vec![("value", FieldValue::Float(*value))]));
+                sample_rate = distribution.sample_rate;
+            },
+            Metric::Set(ref set) => {
+                lines.push(vec![("value", FieldValue::String(&set.value))]);
+                sample_rate = set.sample_rate;
+            },
+            Metric::ServiceCheck(ref service_check) => {
+                let mut fields = vec![("status", FieldValue::Integer(i64::from(service_check.status.code())))];
+                if let Some(ref message) = service_check.message {
+                    fields.push(("message", FieldValue::String(message)));
+                }
+                lines.push(fields);
+                message_timestamp = service_check.timestamp;
+            },
+            Metric::Event(ref event) => {
+                let mut fields = vec![("text", FieldValue::String(&event.text))];
+                if let Some(ref priority) = event.priority {
+                    fields.push(("priority", FieldValue::String(priority.as_str())));
+                }
+                if let Some(ref alert_type) = event.alert_type {
+                    fields.push(("alert_type", FieldValue::String(alert_type.as_str())));
+                }
+                lines.push(fields);
+                message_timestamp = event.timestamp;
+            }
+        }
+
+        let series = self.series(message);
+        let timestamp = message_timestamp.or(timestamp).map(|timestamp| self.timestamp(timestamp));
+
+        lines.into_iter()
+            .filter_map(|mut fields| {
+                fields.retain(|(_, value)| match *value {
+                    FieldValue::Float(value) => value.is_finite(),
+                    _ => true
+                });
+                if fields.is_empty() {
+                    return None
+                }
+                if let Some(sample_rate) = sample_rate {
+                    fields.push(("sample_rate", FieldValue::Float(sample_rate)));
+                }
+                let mut line = series.clone();
+                for (i, &(key, ref value)) in fields.iter().enumerate() {
+                    line.push(if i == 0 { ' ' } else { ',' });
+                    line.push_str(key);
+                    line.push('=');
+                    match *value {
+                        FieldValue::Float(value) => line.push_str(&value.to_string()),
+                        FieldValue::Integer(value) => line.push_str(&format!("{}i", value)),
+                        FieldValue::String(value) => line.push_str(&format!("\"{}\"", escape_string(value)))
+                    }
+                }
+                if let Some(ref timestamp) = timestamp {
+                    line.push(' ');
+                    line.push_str(timestamp);
+                }
+                Some(line)
+            })
+            .collect()
+    }
+
+    /// The measurement and tag set, InfluxDB does not accept
+    /// duplicate keys and recommends sorting the tags by key
+    fn series(&self, message: &Message) -> String {
+        let mut series = escape(&message.name, &[',', ' ']);
+        if let Some(ref tags) = message.tags {
+            let tags: BTreeMap<&str, &str> = tags.iter()
+                .filter_map(|tag| match tag.value {
+                    Some(ref value) if !value.is_empty() => Some((tag.key.as_str(), value.as_str())),
+                    _ => None
+                })
+                .collect();
+            for (key, value) in tags {
+                series.push(',');
+                series.push_str(&escape(key, &[',', '=', ' ']));
+                series.push('=');
+                series.push_str(&escape(value, &[',', '=', ' ']));
+            }
+        }
+        series
+    }
+
+    fn timestamp(&self, timestamp: f64) -> String {
+        let factor = match self.precision {
+            InfluxPrecision::SECONDS => 1.0,
+            InfluxPrecision::MILLISECONDS => 1e3,
+            InfluxPrecision::MICROSECONDS => 1e6,
+            InfluxPrecision::NANOSECONDS => 1e9
+        };
+        format!("{}", (timestamp * factor).round() as i64)
+    }
+}
+
+/// Escapes the given characters with a backslash
+fn escape(input: &str, characters: &[char]) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if characters.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes double quotes and backslashes in string field values
+fn escape_string(input: &str) -> String {
+    escape(input, &['"', '\\'])
+}
+
+#[cfg(test)]
+mod tests {
+    use parse;
+    use super::{InfluxOptions, InfluxPrecision};
+
+    fn format(input: &str) -> Vec<String> {
+        InfluxOptions::default().format(&parse(input).unwrap(), None)
+    }
+
+    #[test]
+    fn test_influx_metrics() {
+        assert_eq!(format("gorets:1|c|@0.5"), vec!["gorets count=1,sample_rate=0.5"]);
+        assert_eq!(format("gorets:1.5|m"), vec!["gorets count=1.5"]);
+        assert_eq!(format("gorets:3|g"), vec!["gorets value=3"]);
+        assert_eq!(format("gorets:233:12.5|ms"), vec!["gorets value=233", "gorets value=12.5"]);
+        assert_eq!(format("gorets:233|h"), vec!["gorets value=233"]);
+        assert_eq!(format("gorets:233|d"), vec!["gorets value=233"]);
+        assert_eq!(format("users.unique:alice|s"), vec!["users.unique value=\"alice\""]);
+    }
+
+    #[test]
+    fn test_influx_relative_gauges() {
+        // Only gauges that are set have a value
+        assert!(format("gorets:+3|g").is_empty());
+        assert!(format("gorets:-3|g").is_empty());
+    }
+
+    #[test]
+    fn test_influx_tags() {
+        assert_eq!(format("gorets:1|c|#foo:bar,moo,env:"), vec!["gorets,foo=bar count=1"]);
+        assert_eq!(format("gorets:1|c|#redis instance:10.0.0.16:6379,a=b:c,d"), vec!["gorets,a\\=b=c,redis\\ instance=10.0.0.16:6379 count=1"]);
+
+        // Tags are sorted by key and the last value of a key is used
+        assert_eq!(format("gorets:1|c|#team:web,env:production,team:ops"), vec!["gorets,env=production,team=ops count=1"]);
+    }
+
+    #[test]
+    fn test_influx_non_finite() {
+        assert!(format("gorets:NaN|g").is_empty());
+        assert_eq!(format("gorets:1:inf:2|ms"), vec!["gorets value=1", "gorets value=2"]);
+    }
+
+    #[test]
+    fn test_influx_escaping() {
+        assert_eq!(format("gor ets,a=b:1|c"), vec!["gor\\ ets\\,a=b count=1"]);
+        assert_eq!(format("users:say \"hi\" \\o/|s"), vec!["users value=\"say \\\"hi\\\" \\\\o/\""]);
+    }
+
+    #[test]
+    fn test_influx_timestamp() {
+        assert_eq!(format("gorets:1|c|T1656581400"), vec!["gorets count=1 1656581400000000000"]);
+
+        let options = InfluxOptions { precision: InfluxPrecision::SECONDS };
+        assert_eq!(options.format(&parse("gorets:1|c").unwrap(), Some(1656581400.0)), vec!["gorets count=1 1656581400"]);
+
+        let options = InfluxOptions { precision: InfluxPrecision::MILLISECONDS };
+        assert_eq!(options.format(&parse("gorets:1|c|T1656581400.5").unwrap(), Some(1.0)), vec!["gorets count=1 1656581400500"]);
+    }
+
+    #[test]
+    fn test_influx_service_check() {
+        assert_eq!(format("_sc|Redis connection|2|d:1656581400|#env:prod|m:timed out"), vec!["Redis\\ connection,env=prod status=2i,message=\"timed out\" 1656581400000000000"]);
+        assert_eq!(format("_sc|Redis connection"), vec!["Redis\\ connection status=3i"]);
+    }
+
+    #[test]
+    fn test_influx_event() {
+        assert_eq!(format("_e{6,5}:deploy|v1 v2|p:low|t:info"), vec!["deploy text=\"v1 v2\",priority=\"low\",alert_type=\"info\""]);
+    }
+}
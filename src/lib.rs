@@ -1,17 +1,52 @@
-use std::collections::BTreeMap;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+use std::str;
+
+mod aggregator;
+mod borrowed;
+mod ddsketch;
+mod display;
+mod graphite;
+mod hyperloglog;
+mod influx;
+mod openmetrics;
 mod parser;
+mod prometheus;
+mod tags;
 
-pub use parser::ParseError;
+pub use aggregator::{Aggregator, AggregatorOptions, OptionsError, Aggregate, AggregateValue, Statistics, Percentile, Flush, Clock, SystemClock};
+pub use borrowed::{MessageRef, MetricRef, SetRef, ServiceCheckRef, EventRef};
+pub use ddsketch::DDSketch;
+pub use graphite::{GraphiteOptions, GraphiteTags};
+pub use hyperloglog::HyperLogLog;
+pub use influx::{InfluxOptions, InfluxPrecision};
+pub use parser::{ParseError, ErrorKind, Field};
+pub use prometheus::{PrometheusRegistry, PrometheusOptions, PrometheusTimer};
+pub use tags::{Tag, TagRef, Tags};
 
 #[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Message {
     pub name: String,
-    pub tags: Option<BTreeMap<String, String>>,
+    pub tags: Option<Tags>,
+    /// Client side timestamp of a metric (`|T`)
+    pub timestamp: Option<f64>,
+    /// Container ID used for origin detection (`|c:`)
+    pub container_id: Option<String>,
+    /// External data used for origin detection (`|e:`)
+    pub external_data: Option<String>,
+    /// Tag cardinality requested by the client (`|card:`)
+    pub cardinality: Option<Cardinality>,
     pub metric: Metric
 }
 
 #[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
 pub enum Metric {
     Gauge(Gauge),
     Counter(Counter),
@@ -20,10 +55,98 @@ pub enum Metric {
     Meter(Meter),
     Distribution(Distribution),
     Set(Set),
-    ServiceCheck(ServiceCheck)
+    ServiceCheck(ServiceCheck),
+    Event(Event)
 }
 
-#[derive(Debug,PartialEq)]
+impl Metric {
+    /// The sample rate of the metric, service checks and events are not sampled
+    pub fn sample_rate(&self) -> Option<f64> {
+        match *self {
+            Metric::Gauge(ref gauge) => gauge.sample_rate,
+            Metric::Counter(ref counter) => counter.sample_rate,
+            Metric::Timing(ref timing) => timing.sample_rate,
+            Metric::Histogram(ref histogram) => histogram.sample_rate,
+            Metric::Meter(ref meter) => meter.sample_rate,
+            Metric::Distribution(ref distribution) => distribution.sample_rate,
+            Metric::Set(ref set) => set.sample_rate,
+            Metric::ServiceCheck(_) | Metric::Event(_) => None
+        }
+    }
+
+    /// The number of messages a message stands for, 1 divided by the
+    /// sample rate, e.g. 10 for a metric with `@0.1`
+    pub fn weight(&self) -> f64 {
+        1.0 / self.sample_rate().unwrap_or(1.0)
+    }
+
+    /// The value of a counter or meter divided by the sample rate, or the
+    /// value of a gauge that is set. Gauges are not scaled, the last value is
+    /// what counts. Gauges that are incremented or decremented and other
+    /// metrics have no single value and return None.
+    pub fn effective_value(&self) -> Option<f64> {
+        match *self {
+            Metric::Counter(ref counter) => Some(counter.value * self.weight()),
+            Metric::Meter(ref meter) => Some(meter.value * self.weight()),
+            Metric::Gauge(ref gauge) if gauge.op == GaugeOp::SET => Some(gauge.value),
+            _ => None
+        }
+    }
+
+    /// The number of observations the metric stands for, every value of a timing,
+    /// histogram or distribution and every other metric counts as 1 divided by the
+    /// sample rate, e.g. 10 for a single timing with `@0.1`
+    pub fn effective_count(&self) -> f64 {
+        let count = match *self {
+            Metric::Timing(ref timing) => timing.values.len(),
+            Metric::Histogram(ref histogram) => histogram.values.len(),
+            Metric::Distribution(ref distribution) => distribution.values.len(),
+            _ => 1
+        };
+        count as f64 * self.weight()
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Cardinality {
+    NONE,
+    LOW,
+    ORCHESTRATOR,
+    HIGH
+}
+
+impl Cardinality {
+    /// The cardinality as it's written in the protocol, e.g. `orchestrator`
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Cardinality::NONE => "none",
+            Cardinality::LOW => "low",
+            Cardinality::ORCHESTRATOR => "orchestrator",
+            Cardinality::HIGH => "high"
+        }
+    }
+}
+
+/// Parses the cardinality as it's written in the protocol
+impl str::FromStr for Cardinality {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Cardinality, ()> {
+        match string {
+            "none" => Ok(Cardinality::NONE),
+            "low" => Ok(Cardinality::LOW),
+            "orchestrator" => Ok(Cardinality::ORCHESTRATOR),
+            "high" => Ok(Cardinality::HIGH),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Status {
     OK,
     WARNING,
@@ -31,49 +154,109 @@ pub enum Status {
     UNKNOWN
 }
 
-#[derive(Debug,PartialEq)]
+impl Status {
+    /// The status code from the protocol, between 0 and 3
+    pub fn code(&self) -> u8 {
+        match *self {
+            Status::OK => 0,
+            Status::WARNING => 1,
+            Status::CRITICAL => 2,
+            Status::UNKNOWN => 3
+        }
+    }
+
+    /// The status code as it's written in the protocol, e.g. `2`
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Status::OK => "0",
+            Status::WARNING => "1",
+            Status::CRITICAL => "2",
+            Status::UNKNOWN => "3"
+        }
+    }
+}
+
+/// Parses the status code as it's written in the protocol
+impl str::FromStr for Status {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Status, ()> {
+        match string {
+            "0" => Ok(Status::OK),
+            "1" => Ok(Status::WARNING),
+            "2" => Ok(Status::CRITICAL),
+            "3" => Ok(Status::UNKNOWN),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum GaugeOp {
+    SET,
+    INCREMENT,
+    DECREMENT
+}
+
+/// A gauge is set to the value, or incremented or decremented by the value
+/// when it is prefixed with a sign (`+5` or `-3`)
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Gauge {
     pub value: f64,
+    pub op: GaugeOp,
     pub sample_rate: Option<f64>,
 }
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Counter {
     pub value: f64,
     pub sample_rate: Option<f64>,
 }
 
-#[derive(Debug,PartialEq)]
+/// Timings, histograms and distributions can contain multiple values
+/// in a single line, e.g. `latency:12:15:9|ms`
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Timing {
-    pub value: f64,
+    pub values: Vec<f64>,
     pub sample_rate: Option<f64>,
 }
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Histogram {
-    pub value: f64,
+    pub values: Vec<f64>,
     pub sample_rate: Option<f64>,
 }
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Meter {
     pub value: f64,
     pub sample_rate: Option<f64>,
 }
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Distribution {
-    pub value: f64,
+    pub values: Vec<f64>,
     pub sample_rate: Option<f64>,
 }
 
+/// The value of a set is kept as a string, so any identifier can be counted
 #[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Set {
-    pub value: f64,
+    pub value: String,
     pub sample_rate: Option<f64>,
 }
 
 #[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ServiceCheck {
     pub status: Status,
     pub timestamp: Option<f64>,
@@ -81,21 +264,193 @@ pub struct ServiceCheck {
     pub message: Option<String>,
 }
 
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Priority {
+    NORMAL,
+    LOW
+}
+
+impl Priority {
+    /// The priority as it's written in the protocol, e.g. `low`
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Priority::NORMAL => "normal",
+            Priority::LOW => "low"
+        }
+    }
+}
+
+/// Parses the priority as it's written in the protocol
+impl str::FromStr for Priority {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Priority, ()> {
+        match string {
+            "normal" => Ok(Priority::NORMAL),
+            "low" => Ok(Priority::LOW),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AlertType {
+    ERROR,
+    WARNING,
+    INFO,
+    SUCCESS
+}
+
+impl AlertType {
+    /// The alert type as it's written in the protocol, e.g. `warning`
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            AlertType::ERROR => "error",
+            AlertType::WARNING => "warning",
+            AlertType::INFO => "info",
+            AlertType::SUCCESS => "success"
+        }
+    }
+}
+
+/// Parses the alert type as it's written in the protocol
+impl str::FromStr for AlertType {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<AlertType, ()> {
+        match string {
+            "error" => Ok(AlertType::ERROR),
+            "warning" => Ok(AlertType::WARNING),
+            "info" => Ok(AlertType::INFO),
+            "success" => Ok(AlertType::SUCCESS),
+            _ => Err(())
+        }
+    }
+}
+
+/// A DogStatsD event, the title of the event is stored as the message name
+#[derive(Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Event {
+    pub text: String,
+    pub timestamp: Option<f64>,
+    pub hostname: Option<String>,
+    pub aggregation_key: Option<String>,
+    pub priority: Option<Priority>,
+    pub source_type: Option<String>,
+    pub alert_type: Option<AlertType>,
+}
+
+/// Options to configure the parser, the default options
+/// are used by `parse`, `parse_borrowed`, `parse_bytes` and `parse_packet`
+#[derive(Debug,Clone,PartialEq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ParserOptions {
+    /// Reject unrecognized sections, trailing separators and invalid
+    /// service check statuses instead of ignoring them
+    pub strict: bool,
+    /// Maximum length of a name in bytes
+    pub max_name_length: Option<usize>,
+    /// Maximum number of tags
+    pub max_tags: Option<usize>,
+    /// Maximum length of a tag key or value in bytes
+    pub max_tag_length: Option<usize>,
+    /// Truncate names and tags and drop tags that exceed the
+    /// limits instead of rejecting them
+    pub truncate: bool,
+}
+
+impl ParserOptions {
+    /// Parse a statsd string and return a metric or error message
+    pub fn parse<S: Into<String>>(&self, input: S) -> Result<Message, ParseError> {
+        self.parse_borrowed(&input.into()).map(|message| message.to_owned())
+    }
+
+    /// Parse a statsd string and return a metric that borrows from the input or error message
+    pub fn parse_borrowed<'a>(&self, input: &'a str) -> Result<MessageRef<'a>, ParseError> {
+        if input.starts_with("_sc") {
+            parser::service_check_parser::parse(input, self)
+        } else if input.starts_with("_e{") {
+            parser::event_parser::parse(input, self)
+        } else {
+            parser::metric_parser::parse(input, self)
+        }
+    }
+
+    /// Parse statsd bytes and return a metric that borrows from the input or error message
+    pub fn parse_bytes<'a>(&self, input: &'a [u8]) -> Result<MessageRef<'a>, ParseError> {
+        match str::from_utf8(input) {
+            Ok(string) => self.parse_borrowed(string),
+            Err(e) => {
+                Err(ParseError {
+                    kind: ErrorKind::InvalidUtf8,
+                    field: Field::Line,
+                    position: e.valid_up_to(),
+                    token: String::new(),
+                    line: String::from_utf8_lossy(input).into_owned()
+                })
+            }
+        }
+    }
+
+    /// Parse a packet with newline separated statsd strings, returns an iterator
+    /// with a metric or error message for every line. Blank lines are skipped.
+    pub fn parse_packet<'a>(&self, input: &'a str) -> Lines<'a> {
+        Lines {
+            lines: input.lines(),
+            options: self.clone()
+        }
+    }
+}
+
 /// Parse a statsd string and return a metric or error message
 pub fn parse<S: Into<String>>(input: S) -> Result<Message, ParseError> {
-    let string = input.into();
+    ParserOptions::default().parse(input)
+}
+
+/// Parse a statsd string and return a metric that borrows from the input or error message
+pub fn parse_borrowed<'a>(input: &'a str) -> Result<MessageRef<'a>, ParseError> {
+    ParserOptions::default().parse_borrowed(input)
+}
+
+/// Parse statsd bytes and return a metric that borrows from the input or error message
+pub fn parse_bytes<'a>(input: &'a [u8]) -> Result<MessageRef<'a>, ParseError> {
+    ParserOptions::default().parse_bytes(input)
+}
 
-    if string.starts_with("_sc") {
-        parser::service_check_parser::parse(string)
-    } else {
-        parser::metric_parser::parse(string)
+/// Iterator over the messages in a packet, see `parse_packet`
+#[derive(Debug)]
+pub struct Lines<'a> {
+    lines: str::Lines<'a>,
+    options: ParserOptions
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Result<Message, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let options = &self.options;
+        self.lines
+            .by_ref()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| options.parse_borrowed(line).map(|message| message.to_owned()))
     }
 }
 
+/// Parse a packet with newline separated statsd strings, returns an iterator
+/// with a metric or error message for every line. Blank lines are skipped.
+pub fn parse_packet<'a>(input: &'a str) -> Lines<'a> {
+    ParserOptions::default().parse_packet(input)
+}
+
 #[cfg(test)]
 mod tests {
     use {Message, Metric};
-    use std::collections::BTreeMap;
 
     use super::*;
 
@@ -104,6 +459,10 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: None,
@@ -118,6 +477,10 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: None,
@@ -132,8 +495,13 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Gauge(Gauge {
                 value: 1.0,
+                op: GaugeOp::SET,
                 sample_rate: None,
             })
         };
@@ -141,13 +509,36 @@ mod tests {
         assert_eq!(parse("gorets:1|g"), Ok(expected));
     }
 
+    #[test]
+    fn test_statsd_gauge_decrement() {
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Gauge(Gauge {
+                value: 3.0,
+                op: GaugeOp::DECREMENT,
+                sample_rate: None,
+            })
+        };
+
+        assert_eq!(parse("gorets:-3|g"), Ok(expected));
+    }
+
     #[test]
     fn test_statsd_time() {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Timing(Timing {
-                value: 233.0,
+                values: vec![233.0],
                 sample_rate: None,
             })
         };
@@ -160,8 +551,12 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Histogram(Histogram {
-                value: 233.0,
+                values: vec![233.0],
                 sample_rate: None,
             })
         };
@@ -174,8 +569,12 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Distribution(Distribution {
-                value: 233.0,
+                values: vec![233.0],
                 sample_rate: None,
             })
         };
@@ -188,8 +587,12 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Set(Set {
-                value: 233.0,
+                value: "233".to_string(),
                 sample_rate: None,
             })
         };
@@ -202,6 +605,10 @@ mod tests {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Meter(Meter {
                 value: 233.0,
                 sample_rate: None,
@@ -211,11 +618,33 @@ mod tests {
         assert_eq!(parse("gorets:233|m"), Ok(expected));
     }
 
+    #[test]
+    fn test_statsd_histogram_multiple_values() {
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Histogram(Histogram {
+                values: vec![233.0, 12.5, 7.0],
+                sample_rate: None,
+            })
+        };
+
+        assert_eq!(parse("gorets:233:12.5:7|h"), Ok(expected));
+    }
+
     #[test]
     fn test_statsd_counter_with_sample_rate() {
         let expected = Message {
             name: "gorets".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: Some(0.5),
@@ -227,12 +656,16 @@ mod tests {
 
     #[test]
     fn test_statsd_counter_with_key_value_tags() {
-        let mut tags = BTreeMap::new();
-        tags.insert("foo".to_string(), "bar".to_string());
+        let mut tags = Tags::new();
+        tags.push("foo", "bar");
 
         let expected = Message {
             name: "gorets".to_string(),
             tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: None,
@@ -244,13 +677,17 @@ mod tests {
 
     #[test]
     fn test_statsd_counter_with_key_tags() {
-        let mut tags = BTreeMap::new();
-        tags.insert("foo".to_string(), "".to_string());
-        tags.insert("moo".to_string(), "".to_string());
+        let mut tags = Tags::new();
+        tags.push_key("foo");
+        tags.push_key("moo");
 
         let expected = Message {
             name: "gorets".to_string(),
             tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: None,
@@ -262,13 +699,17 @@ mod tests {
 
     #[test]
     fn test_statsd_counter_with_sample_rate_and_tags() {
-        let mut tags = BTreeMap::new();
-        tags.insert("foo".to_string(), "bar".to_string());
-        tags.insert("moo".to_string(), "maa".to_string());
+        let mut tags = Tags::new();
+        tags.push("foo", "bar");
+        tags.push("moo", "maa");
 
         let expected = Message {
             name: "gorets".to_string(),
             tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: Some(0.9),
@@ -278,11 +719,73 @@ mod tests {
         assert_eq!(parse("gorets:1|c|@0.9|#foo:bar,moo:maa"), Ok(expected));
     }
 
+    #[test]
+    fn test_statsd_gauge_with_timestamp() {
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: None,
+            timestamp: Some(1656581400f64),
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Gauge(Gauge {
+                value: 1.0,
+                op: GaugeOp::SET,
+                sample_rate: None,
+            })
+        };
+
+        assert_eq!(parse("gorets:1|g|T1656581400"), Ok(expected));
+    }
+
+    #[test]
+    fn test_statsd_counter_with_origin_detection() {
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: Some("83c0a99c0a54c0c187f461c7980e9b57f3f6a8b0c918c8d93df19a9de6f3fe1d".to_string()),
+            external_data: Some("it-false,cn-redis,pu-810fe89d".to_string()),
+            cardinality: Some(Cardinality::HIGH),
+            metric: Metric::Counter(Counter {
+                value: 1.0,
+                sample_rate: None,
+            })
+        };
+
+        assert_eq!(parse("gorets:1|c|c:83c0a99c0a54c0c187f461c7980e9b57f3f6a8b0c918c8d93df19a9de6f3fe1d|e:it-false,cn-redis,pu-810fe89d|card:high"), Ok(expected));
+    }
+
+    #[test]
+    fn test_statsd_counter_with_tags_and_sample_rate() {
+        let mut tags = Tags::new();
+        tags.push("a", "b");
+
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Counter(Counter {
+                value: 1.0,
+                sample_rate: Some(0.5),
+            })
+        };
+
+        assert_eq!(parse("gorets:1|c|#a:b|@0.5"), Ok(expected));
+    }
+
     #[test]
     fn test_statsd_utf8_boundary() {
         let expected = Message {
             name: "goretsβ".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Counter(Counter {
                 value: 1.0,
                 sample_rate: None,
@@ -292,28 +795,314 @@ mod tests {
         assert_eq!(parse("goretsβ:1|c"), Ok(expected));
     }
 
+    #[test]
+    fn test_statsd_event() {
+        let expected = Message {
+            name: "deploy".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Event(Event {
+                text: "v1|v2".to_string(),
+                timestamp: None,
+                hostname: Some("frontend1".to_string()),
+                aggregation_key: None,
+                priority: None,
+                source_type: None,
+                alert_type: Some(AlertType::INFO),
+            })
+        };
+
+        assert_eq!(parse("_e{6,5}:deploy|v1|v2|h:frontend1|t:info"), Ok(expected));
+    }
+
+    #[test]
+    fn test_statsd_borrowed() {
+        let input = "gorets:1|c|#foo:bar|c:3ac6ec42".to_string();
+
+        let tags = vec![TagRef { key: "foo", value: Some("bar") }];
+
+        let expected = MessageRef {
+            name: "gorets",
+            tags: Some(tags),
+            timestamp: None,
+            container_id: Some("3ac6ec42"),
+            external_data: None,
+            cardinality: None,
+            metric: MetricRef::Counter(Counter {
+                value: 1.0,
+                sample_rate: None,
+            })
+        };
+
+        let message = parse_borrowed(&input).unwrap();
+        assert_eq!(message, expected);
+
+        // The name is a slice of the input
+        assert_eq!(message.name.as_ptr(), input.as_ptr());
+
+        assert_eq!(Ok(message.to_owned()), parse(input.clone()));
+    }
+
+    #[test]
+    fn test_statsd_bytes() {
+        let expected = MessageRef {
+            name: "users",
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: MetricRef::Set(SetRef {
+                value: "alice",
+                sample_rate: None,
+            })
+        };
+
+        assert_eq!(parse_bytes(b"users:alice|s"), Ok(expected));
+        assert_eq!(parse_bytes(b"users:\xff|s").map_err(|e| e.kind), Err(ErrorKind::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_statsd_packet() {
+        let mut lines = parse_packet("gorets:1|c\n\ngorets:aaa|h\r\n  \ngorets:233|s\n");
+
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Counter(Counter {
+                value: 1.0,
+                sample_rate: None,
+            })
+        };
+        assert_eq!(lines.next(), Some(Ok(expected)));
+
+        assert_eq!(lines.next().unwrap().map_err(|e| e.kind), Err(ErrorKind::ValueNotFloat));
+
+        let expected = Message {
+            name: "gorets".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Set(Set {
+                value: "233".to_string(),
+                sample_rate: None,
+            })
+        };
+        assert_eq!(lines.next(), Some(Ok(expected)));
+
+        assert_eq!(lines.next(), None);
+    }
+
     #[test]
     fn test_statsd_empty() {
-        assert_eq!(parse(""), Err(ParseError::EmptyInput));
+        assert_eq!(parse("").map_err(|e| e.kind), Err(ErrorKind::EmptyInput));
     }
 
     #[test]
     fn test_statsd_no_name() {
-        assert_eq!(parse(":1|c"), Err(ParseError::NoName));
+        assert_eq!(parse(":1|c").map_err(|e| e.kind), Err(ErrorKind::NoName));
     }
 
     #[test]
     fn test_statsd_value_not_float() {
-        assert_eq!(parse("gorets:aaa|h"), Err(ParseError::ValueNotFloat));
+        assert_eq!(parse("gorets:aaa|h").map_err(|e| e.kind), Err(ErrorKind::ValueNotFloat));
     }
 
     #[test]
     fn test_statsd_sample_rate_not_float() {
-        assert_eq!(parse("gorets:1|c|@aaa"), Err(ParseError::SampleRateNotFloat));
+        assert_eq!(parse("gorets:1|c|@aaa").map_err(|e| e.kind), Err(ErrorKind::SampleRateNotFloat));
+    }
+
+    #[test]
+    fn test_statsd_invalid_sample_rate() {
+        assert!(parse("gorets:1|c|@1").is_ok());
+        assert_eq!(parse("gorets:1|c|@5").map_err(|e| e.kind), Err(ErrorKind::InvalidSampleRate));
+        assert_eq!(parse("gorets:1|c|@-1").map_err(|e| e.kind), Err(ErrorKind::InvalidSampleRate));
+        assert_eq!(parse("gorets:1|c|@0").map_err(|e| e.kind), Err(ErrorKind::InvalidSampleRate));
+        assert_eq!(parse("gorets:1|c|@NaN").map_err(|e| e.kind), Err(ErrorKind::InvalidSampleRate));
+
+        let error = parse("gorets:1|c|@5|#foo:bar").unwrap_err();
+        assert_eq!(error.field, Field::SampleRate);
+        assert_eq!(error.position, 12);
+        assert_eq!(error.token, "5");
+    }
+
+    #[test]
+    fn test_statsd_effective_value() {
+        let metric = |input: &str| parse(input).unwrap().metric;
+
+        assert_eq!(metric("gorets:2|c|@0.5").effective_value(), Some(4.0));
+        assert_eq!(metric("gorets:2|c").effective_value(), Some(2.0));
+        assert_eq!(metric("gorets:3|m|@0.1").effective_value(), Some(30.0));
+        assert_eq!(metric("gorets:3|g|@0.5").effective_value(), Some(3.0));
+        assert_eq!(metric("gorets:+3|g").effective_value(), None);
+        assert_eq!(metric("gorets:-3|g").effective_value(), None);
+        assert_eq!(metric("gorets:3:4|ms|@0.5").effective_value(), None);
+        assert_eq!(metric("_sc|Redis connection|0").effective_value(), None);
+    }
+
+    #[test]
+    fn test_statsd_effective_count() {
+        let metric = |input: &str| parse(input).unwrap().metric;
+
+        assert_eq!(metric("gorets:3:4|ms|@0.5").effective_count(), 4.0);
+        assert_eq!(metric("gorets:3|h").effective_count(), 1.0);
+        assert_eq!(metric("gorets:3:4:5|d|@0.25").effective_count(), 12.0);
+        assert_eq!(metric("gorets:2|c|@0.5").effective_count(), 2.0);
+        assert_eq!(metric("users:alice|s").effective_count(), 1.0);
+        assert_eq!(metric("gorets:2|c|@0.5").sample_rate(), Some(0.5));
+        assert_eq!(metric("gorets:2|c|@0.5").weight(), 2.0);
+        assert_eq!(metric("gorets:2|c").weight(), 1.0);
+        assert_eq!(metric("_e{6,5}:deploy|v1|v2").sample_rate(), None);
+    }
+
+    #[test]
+    fn test_statsd_protocol_strings() {
+        assert_eq!(Cardinality::ORCHESTRATOR.as_str(), "orchestrator");
+        assert_eq!("orchestrator".parse(), Ok(Cardinality::ORCHESTRATOR));
+        assert_eq!(Status::CRITICAL.as_str(), "2");
+        assert_eq!(Status::CRITICAL.code(), 2);
+        assert_eq!("2".parse(), Ok(Status::CRITICAL));
+        assert_eq!(Priority::LOW.as_str(), "low");
+        assert_eq!("low".parse(), Ok(Priority::LOW));
+        assert_eq!(AlertType::SUCCESS.as_str(), "success");
+        assert_eq!("success".parse(), Ok(AlertType::SUCCESS));
+        assert_eq!("fatal".parse::<AlertType>(), Err(()));
+    }
+
+    #[test]
+    fn test_statsd_error_context() {
+        let expected = ParseError {
+            kind: ErrorKind::SampleRateNotFloat,
+            field: Field::SampleRate,
+            position: 12,
+            token: "aaa".to_string(),
+            line: "gorets:1|c|@aaa|#foo:bar".to_string(),
+        };
+
+        assert_eq!(parse("gorets:1|c|@aaa|#foo:bar"), Err(expected));
+    }
+
+    #[test]
+    fn test_statsd_error_display() {
+        let error = parse("gorets:1|wrong|@0.5").unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Unknown metric type while parsing metric type at position 9\ngorets:1|wrong|@0.5\n         ^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_statsd_strict() {
+        let options = ParserOptions { strict: true, ..Default::default() };
+
+        assert!(options.parse("gorets:1|c|@0.5").is_ok());
+        assert_eq!(options.parse("gorets:1|c|junk|more").map_err(|e| e.kind), Err(ErrorKind::UnknownSection));
+        assert_eq!(options.parse("gorets:1|c|@0.5|").map_err(|e| e.kind), Err(ErrorKind::TrailingSeparator));
+        assert_eq!(options.parse("_sc|Redis connection|5").map_err(|e| e.kind), Err(ErrorKind::InvalidStatus));
+
+        // Lenient mode ignores the same input
+        assert!(parse("gorets:1|c|junk|more").is_ok());
+        assert!(parse("gorets:1|c|@0.5|").is_ok());
+        assert!(parse("_sc|Redis connection|5").is_ok());
+    }
+
+    #[test]
+    fn test_statsd_limits() {
+        let options = ParserOptions {
+            max_name_length: Some(4),
+            max_tags: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(options.parse("gorets:1|c").map_err(|e| e.kind), Err(ErrorKind::NameTooLong));
+        assert_eq!(options.parse("gore:1|c|#foo:bar,moo:maa").map_err(|e| e.kind), Err(ErrorKind::TooManyTags));
+        assert_eq!(options.parse("_sc|Redis connection|0").map_err(|e| e.kind), Err(ErrorKind::NameTooLong));
+        assert_eq!(options.parse("_e{6,4}:deploy|text").map_err(|e| e.kind), Err(ErrorKind::NameTooLong));
+
+        let options = ParserOptions { truncate: true, ..options };
+
+        let mut tags = Tags::new();
+        tags.push("foo", "bar");
+
+        let expected = Message {
+            name: "gore".to_string(),
+            tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Counter(Counter {
+                value: 1.0,
+                sample_rate: None,
+            })
+        };
+
+        assert_eq!(options.parse("gorets:1|c|#foo:bar,moo:maa"), Ok(expected));
     }
 
     #[test]
     fn test_statsd_metric_type_unknown() {
-        assert_eq!(parse("gorets:1|wrong"), Err(ParseError::UnknownMetricType));
+        assert_eq!(parse("gorets:1|wrong").map_err(|e| e.kind), Err(ErrorKind::UnknownMetricType));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_statsd_serialize_json() {
+        let message = parse("gorets:1|c|@0.5|#foo:bar,moo|card:low").unwrap();
+        let expected = r#"{"name":"gorets","tags":[{"key":"foo","value":"bar"},{"key":"moo","value":null}],"timestamp":null,"container_id":null,"external_data":null,"cardinality":"low","metric":{"type":"counter","value":1.0,"sample_rate":0.5}}"#;
+
+        assert_eq!(serde_json::to_string(&message).unwrap(), expected);
+
+        // The borrowed message has the same shape
+        let message = parse_borrowed("gorets:1|c|@0.5|#foo:bar,moo|card:low").unwrap();
+        assert_eq!(serde_json::to_string(&message).unwrap(), expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_statsd_serialize_json_service_check() {
+        let message = parse("_sc|Redis connection|2|h:frontend1|m:timeout").unwrap();
+        let expected = r#"{"name":"Redis connection","tags":null,"timestamp":null,"container_id":null,"external_data":null,"cardinality":null,"metric":{"type":"service_check","status":"critical","timestamp":null,"hostname":"frontend1","message":"timeout"}}"#;
+
+        assert_eq!(serde_json::to_string(&message).unwrap(), expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_statsd_deserialize_json() {
+        let lines = vec![
+            "gorets:-3|g|#foo",
+            "gorets:233:12.5|ms|T1656581400",
+            "gorets:233|h|@0.5",
+            "gorets:233|m",
+            "latency:12:15:9|d|c:3ac6ec42|e:it-false",
+            "users.unique:alice@example.com|s",
+            "_sc|Redis connection|0|d:10101",
+            "_e{6,5}:deploy|v1|v2|p:low|t:warning|#foo:",
+        ];
+
+        for line in lines {
+            let message = parse(line).unwrap();
+            let json = serde_json::to_string(&message).unwrap();
+            assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), message, "{}", line);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_statsd_deserialize_options() {
+        let options: ParserOptions = serde_json::from_str(r#"{"strict":true,"max_tags":10}"#).unwrap();
+        assert_eq!(options, ParserOptions { strict: true, max_tags: Some(10), ..Default::default() });
     }
 }
@@ -0,0 +1,267 @@
+use std::collections::BTreeSet;
+use std::f64;
+
+use Clock;
+use prometheus::{PrometheusRegistry, FamilyType, Value, summary_quantile, format_value, write_sample};
+
+impl<C: Clock> PrometheusRegistry<C> {
+    /// Renders the registry in the OpenMetrics 1.0 text format. Counters get a
+    /// `_total` suffix, names get the unit as suffix and counters, histograms and
+    /// summaries get a `_created` timestamp.
+    ///
+    /// OpenMetrics does not allow families with the same name or with samples that
+    /// have the same name, like a counter `errors_total` and a gauge `errors`. Only
+    /// the first of these families, sorted by their Prometheus name, is rendered.
+    pub fn render_openmetrics(&self) -> String {
+        let mut output = String::new();
+        let mut used_names = BTreeSet::new();
+
+        for (name, family) in &self.families {
+            let mut name = name.clone();
+            if family.family_type == FamilyType::Counter && name.ends_with("_total") {
+                let len = name.len() - "_total".len();
+                name.truncate(len);
+            }
+            if let Some(ref unit) = family.unit {
+                if !name.ends_with(&format!("_{}", unit)) {
+                    name.push('_');
+                    name.push_str(unit);
+                }
+            }
+
+            let names = family_names(&name, family.family_type);
+            if names.iter().any(|name| used_names.contains(name)) {
+                continue;
+            }
+            used_names.extend(names);
+
+            let family_type = match family.family_type {
+                FamilyType::Counter => "counter",
+                FamilyType::Gauge => "gauge",
+                FamilyType::Histogram => "histogram",
+                FamilyType::Summary => "summary"
+            };
+
+            output.push_str(&format!("# TYPE {} {}\n", name, family_type));
+            if let Some(ref unit) = family.unit {
+                output.push_str(&format!("# UNIT {} {}\n", name, unit));
+            }
+            output.push_str(&format!("# HELP {} {}\n", name, escape_help(&family.help)));
+
+            for (labels, series) in &family.series {
+                match series.value {
+                    Value::Counter(value) => {
+                        write_sample(&mut output, &name, "_total", labels, None, value);
+                        write_sample(&mut output, &name, "_created", labels, None, series.created);
+                    },
+                    Value::Gauge(value) => {
+                        write_sample(&mut output, &name, "", labels, None, value);
+                    },
+                    Value::Histogram { ref buckets, count, sum } => {
+                        for (bucket, upper_bound) in buckets.iter().zip(self.options.buckets.iter()) {
+                            write_sample(&mut output, &name, "_bucket", labels, Some(("le", format_float(*upper_bound))), *bucket);
+                        }
+                        write_sample(&mut output, &name, "_bucket", labels, Some(("le", format_float(f64::INFINITY))), count);
+                        write_sample(&mut output, &name, "_count", labels, None, count);
+                        write_sample(&mut output, &name, "_sum", labels, None, sum);
+                        write_sample(&mut output, &name, "_created", labels, None, series.created);
+                    },
+                    Value::Summary(ref sketch) => {
+                        for quantile in &self.options.quantiles {
+                            write_sample(&mut output, &name, "", labels, Some(("quantile", format_float(*quantile))), summary_quantile(sketch, *quantile));
+                        }
+                        write_sample(&mut output, &name, "_count", labels, None, sketch.count());
+                        write_sample(&mut output, &name, "_sum", labels, None, sketch.sum());
+                        write_sample(&mut output, &name, "_created", labels, None, series.created);
+                    }
+                }
+            }
+        }
+        output.push_str("# EOF\n");
+        output
+    }
+}
+
+/// The name of a family and the names of its samples
+fn family_names(name: &str, family_type: FamilyType) -> Vec<String> {
+    let suffixes: &[&str] = match family_type {
+        FamilyType::Counter => &["", "_total", "_created"],
+        FamilyType::Gauge => &[""],
+        FamilyType::Histogram => &["", "_bucket", "_count", "_sum", "_created"],
+        FamilyType::Summary => &["", "_count", "_sum", "_created"]
+    };
+    suffixes.iter().map(|suffix| format!("{}{}", name, suffix)).collect()
+}
+
+/// Formats a float in the canonical form that OpenMetrics
+/// requires for the `le` and `quantile` labels, e.g. `1.0`
+fn format_float(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        format_value(value)
+    }
+}
+
+/// Escapes backslashes, double quotes and newlines in help texts
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use parse;
+    use {PrometheusRegistry, PrometheusOptions, PrometheusTimer};
+
+    fn registry(options: PrometheusOptions, lines: &[&str]) -> PrometheusRegistry {
+        let mut registry = PrometheusRegistry::new(options);
+        for (i, line) in lines.iter().enumerate() {
+            registry.record_at(&parse(*line).unwrap(), 1656581400.0 + i as f64);
+        }
+        registry
+    }
+
+    #[test]
+    fn test_openmetrics_empty() {
+        assert_eq!(PrometheusRegistry::default().render_openmetrics(), "# EOF\n");
+    }
+
+    #[test]
+    fn test_openmetrics_counter() {
+        let registry = registry(PrometheusOptions::default(), &[
+            "api.requests:1|c|#method:get",
+            "api.requests:2|c|@0.5|#method:post",
+            "errors_total:1|c",
+        ]);
+
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE api_requests counter
+# HELP api_requests StatsD metric api.requests
+api_requests_total{method=\"get\"} 1
+api_requests_created{method=\"get\"} 1656581400
+api_requests_total{method=\"post\"} 4
+api_requests_created{method=\"post\"} 1656581401
+# TYPE errors counter
+# HELP errors StatsD metric errors_total
+errors_total 1
+errors_created 1656581402
+# EOF
+");
+    }
+
+    #[test]
+    fn test_openmetrics_gauge() {
+        let mut registry = registry(PrometheusOptions::default(), &[
+            "memory:2048|g",
+        ]);
+        registry.set_unit("memory", "bytes");
+        registry.set_help("memory", "Memory in \"bytes\"");
+
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE memory_bytes gauge
+# UNIT memory_bytes bytes
+# HELP memory_bytes Memory in \\\"bytes\\\"
+memory_bytes 2048
+# EOF
+");
+    }
+
+    #[test]
+    fn test_openmetrics_name_collision() {
+        let mut registry = registry(PrometheusOptions::default(), &[
+            "errors_total:1|c",
+            "errors:1|g",
+            "memory:2048|g",
+            "memory_bytes:1024|g",
+        ]);
+        registry.set_unit("memory", "bytes");
+
+        // The counter `errors_total` and the gauge `memory_bytes` would get the
+        // same name as a family that comes first
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE errors gauge
+# HELP errors StatsD metric errors
+errors 1
+# TYPE memory_bytes gauge
+# UNIT memory_bytes bytes
+# HELP memory_bytes StatsD metric memory
+memory_bytes 2048
+# EOF
+");
+    }
+
+    #[test]
+    fn test_openmetrics_histogram() {
+        let options = PrometheusOptions { buckets: vec![0.1, 1.0], ..Default::default() };
+        let registry = registry(options, &[
+            "latency:50:500|ms",
+        ]);
+
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE latency_seconds histogram
+# UNIT latency_seconds seconds
+# HELP latency_seconds StatsD metric latency
+latency_seconds_bucket{le=\"0.1\"} 1
+latency_seconds_bucket{le=\"1.0\"} 2
+latency_seconds_bucket{le=\"+Inf\"} 2
+latency_seconds_count 2
+latency_seconds_sum 0.55
+latency_seconds_created 1656581400
+# EOF
+");
+    }
+
+    #[test]
+    fn test_openmetrics_summary() {
+        let options = PrometheusOptions { timer_type: PrometheusTimer::SUMMARY, quantiles: vec![0.5, 1.0], ..Default::default() };
+        let registry = registry(options, &[
+            "size:1:2:3:4|h",
+        ]);
+
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE size summary
+# HELP size StatsD metric size
+size{quantile=\"0.5\"} 1.993661701417345
+size{quantile=\"1.0\"} 4
+size_count 4
+size_sum 10
+size_created 1656581400
+# EOF
+");
+    }
+
+    #[test]
+    fn test_openmetrics_clock() {
+        let mut registry = PrometheusRegistry::with_clock(PrometheusOptions::default(), || 1656581400.0);
+        registry.record(&parse("errors:1|c").unwrap());
+
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE errors counter
+# HELP errors StatsD metric errors
+errors_total 1
+errors_created 1656581400
+# EOF
+");
+    }
+
+    #[test]
+    fn test_openmetrics_label_escaping() {
+        let mut registry = PrometheusRegistry::default();
+        let mut message = parse("gorets:1|g|#path:C:\\dir\"s\"").unwrap();
+        registry.record_at(&message, 1656581400.0);
+
+        // Tags that are added after parsing can contain newlines
+        if let Some(ref mut tags) = message.tags {
+            tags.push("text", "line\nbreak");
+        }
+        registry.record_at(&message, 1656581400.0);
+
+        assert_eq!(registry.render_openmetrics(), "\
+# TYPE gorets gauge
+# HELP gorets StatsD metric gorets
+gorets{path=\"C:\\\\dir\\\"s\\\"\"} 1
+gorets{path=\"C:\\\\dir\\\"s\\\"\",text=\"line\\nbreak\"} 1
+# EOF
+");
+    }
+}
@@ -0,0 +1,317 @@
+use {ParserOptions, MessageRef, MetricRef, EventRef};
+use super::{Parser, ParseError, ErrorKind, Field};
+
+pub trait EventParser<'a> {
+    fn parse(self) -> Result<MessageRef<'a>, ParseError>;
+}
+
+impl<'a> EventParser<'a> for Parser<'a> {
+    fn parse(mut self) -> Result<MessageRef<'a>, ParseError> {
+        if self.buf.is_empty() {
+            return Err(self.error(ErrorKind::EmptyInput, Field::Line, self.buf))
+        }
+
+        // Skip the event tag and the opening brace (`_e{`)
+        self.take_until(&['{']);
+
+        // The header contains the byte lengths of the title and text,
+        // in the format `{title_length,text_length}:`
+        let title_length = self.take_until(&[',']);
+        let title_length = match title_length.parse::<usize>() {
+            Ok(v) => v,
+            Err(_) => return Err(self.error(ErrorKind::InvalidEventHeader, Field::EventHeader, title_length))
+        };
+        let text_length = self.take_until(&['}']);
+        let text_length = match text_length.parse::<usize>() {
+            Ok(v) => v,
+            Err(_) => return Err(self.error(ErrorKind::InvalidEventHeader, Field::EventHeader, text_length))
+        };
+        if Some(':') != self.peek() {
+            return Err(self.error(ErrorKind::InvalidEventHeader, Field::EventHeader, self.peek_until(&['|'])))
+        }
+        self.skip(); // Skip the `:`
+
+        // Use the lengths from the header to get the title and text,
+        // so pipes in the text don't end the text early
+        let rest = self.rest();
+        let name = match self.take_bytes(title_length) {
+            Ok(v) => v,
+            Err(kind) => return Err(self.error(kind, Field::EventTitle, rest))
+        };
+        if name.is_empty() {
+            return Err(self.error(ErrorKind::NoName, Field::EventTitle, name))
+        }
+        let name = self.check_length(name, self.options.max_name_length, ErrorKind::NameTooLong, Field::EventTitle)?;
+        if Some('|') != self.peek() {
+            return Err(self.error(ErrorKind::InvalidEventHeader, Field::EventTitle, self.peek_until(&['|'])))
+        }
+        self.skip(); // Skip the `|`
+
+        let rest = self.rest();
+        let text = match self.take_bytes(text_length) {
+            Ok(v) => v,
+            Err(kind) => return Err(self.error(kind, Field::EventText, rest))
+        };
+
+        // The text can end with a pipe or whitespace, so only look
+        // for trailing whitespace and a separator after the text
+        self.trim_end();
+        let sections_start = self.pos;
+        match self.peek() {
+            Some('|') => self.skip(),
+            Some(_) => return Err(self.error(ErrorKind::InvalidEventHeader, Field::EventText, self.peek_until(&['|']))),
+            None => ()
+        }
+
+        let mut timestamp = None;
+        let mut hostname = None;
+        let mut aggregation_key = None;
+        let mut priority = None;
+        let mut source_type = None;
+        let mut alert_type = None;
+        let mut tags = None;
+
+        // The remaining optional sections can be in any order, sections that
+        // are not recognized are skipped unless we're in strict mode
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                if tags.is_some() {
+                    return Err(self.error(ErrorKind::DuplicateSection, Field::Section, self.peek_until(&['|'])))
+                }
+                tags = Some(self.parse_tags()?);
+                continue
+            }
+
+            let section = self.take_until(&['|']);
+            let value = section.get(2..).unwrap_or("");
+            match section.get(..2) {
+                Some("d:") => {
+                    timestamp = match value.parse() {
+                        Ok(v) => Some(v),
+                        Err(_) => return Err(self.error(ErrorKind::TimestampNotFloat, Field::Timestamp, value))
+                    }
+                },
+                Some("h:") => hostname = Some(value),
+                Some("k:") => aggregation_key = Some(value),
+                Some("p:") => {
+                    priority = match value.parse() {
+                        Ok(v) => Some(v),
+                        Err(_) if self.options.strict => return Err(self.error(ErrorKind::InvalidPriority, Field::Section, value)),
+                        Err(_) => None
+                    }
+                },
+                Some("s:") => source_type = Some(value),
+                Some("t:") => {
+                    alert_type = match value.parse() {
+                        Ok(v) => Some(v),
+                        Err(_) if self.options.strict => return Err(self.error(ErrorKind::InvalidAlertType, Field::Section, value)),
+                        Err(_) => None
+                    }
+                },
+                _ if self.options.strict => return Err(self.error(ErrorKind::UnknownSection, Field::Section, section)),
+                _ => ()
+            }
+        }
+
+        if self.buf.len() > sections_start {
+            self.check_trailing_separator()?;
+        }
+
+        let event = EventRef {
+            text,
+            timestamp,
+            hostname,
+            aggregation_key,
+            priority,
+            source_type,
+            alert_type
+        };
+
+        Ok(MessageRef {
+            name,
+            tags,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: MetricRef::Event(event)
+        })
+    }
+}
+
+pub fn parse<'a>(input: &'a str, options: &ParserOptions) -> Result<MessageRef<'a>, ParseError> {
+    Parser::new_untrimmed(input, options.clone()).parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use {ParserOptions, Message, Tags, Metric, Event, Priority, AlertType, ParseError, ErrorKind};
+
+    fn parse(input: &str) -> Result<Message, ParseError> {
+        super::parse(input, &ParserOptions::default()).map(|message| message.to_owned())
+    }
+
+    fn parse_strict(input: &str) -> Result<Message, ParseError> {
+        super::parse(input, &ParserOptions { strict: true, ..Default::default() }).map(|message| message.to_owned())
+    }
+
+    #[test]
+    fn test_parse_with_all_fields() {
+        let result = parse("_e{21,36}:An exception occurred|Cannot parse CSV file from 10.0.0.17|d:1656581400|h:frontend1|k:csv|p:low|s:user|t:warning|#err_type:bad_file");
+
+        let mut tags = Tags::new();
+        tags.push("err_type", "bad_file");
+
+        let expected = Message {
+            name: "An exception occurred".to_string(),
+            tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Event(Event {
+                text: "Cannot parse CSV file from 10.0.0.17".to_string(),
+                timestamp: Some(1656581400f64),
+                hostname: Some("frontend1".to_string()),
+                aggregation_key: Some("csv".to_string()),
+                priority: Some(Priority::LOW),
+                source_type: Some("user".to_string()),
+                alert_type: Some(AlertType::WARNING),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_sections_in_any_order() {
+        let result = parse("_e{5,4}:title|text|#foo:bar|t:error|h:frontend1");
+
+        let mut tags = Tags::new();
+        tags.push("foo", "bar");
+
+        let expected = Message {
+            name: "title".to_string(),
+            tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Event(Event {
+                text: "text".to_string(),
+                timestamp: None,
+                hostname: Some("frontend1".to_string()),
+                aggregation_key: None,
+                priority: None,
+                source_type: None,
+                alert_type: Some(AlertType::ERROR),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_pipes_in_text() {
+        let result = parse("_e{5,8}:title|te|x|t|s|p:normal");
+
+        let expected = Message {
+            name: "title".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Event(Event {
+                text: "te|x|t|s".to_string(),
+                timestamp: None,
+                hostname: None,
+                aggregation_key: None,
+                priority: Some(Priority::NORMAL),
+                source_type: None,
+                alert_type: None,
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_utf8_lengths() {
+        let result = parse("_e{7,3}:titleβ|tξ");
+
+        let expected = Message {
+            name: "titleβ".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Event(Event {
+                text: "tξ".to_string(),
+                timestamp: None,
+                hostname: None,
+                aggregation_key: None,
+                priority: None,
+                source_type: None,
+                alert_type: None,
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_trailing_whitespace() {
+        // Whitespace that is part of the text is kept, other trailing whitespace is not
+        let text = |input| parse(input).map(|message| match message.metric {
+            Metric::Event(event) => event.text,
+            _ => unreachable!()
+        });
+        assert_eq!(text("_e{5,6}:title|text  \n"), Ok("text  ".to_string()));
+        assert_eq!(text("_e{5,4}:title|text  \r\n"), Ok("text".to_string()));
+        assert_eq!(parse("_e{5,4}:title|text|h:frontend1  ").map(|message| message.metric), parse("_e{5,4}:title|text|h:frontend1").map(|message| message.metric));
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        assert!(parse_strict("_e{5,5}:title|text|").is_ok());
+        assert!(parse_strict("_e{5,4}:title|text|h:frontend1|#foo:bar").is_ok());
+        assert_eq!(parse_strict("_e{5,4}:title|text|x:unknown").map_err(|e| e.kind), Err(ErrorKind::UnknownSection));
+        assert_eq!(parse_strict("_e{5,4}:title|text|").map_err(|e| e.kind), Err(ErrorKind::TrailingSeparator));
+        assert_eq!(parse_strict("_e{5,4}:title|text|h:frontend1|").map_err(|e| e.kind), Err(ErrorKind::TrailingSeparator));
+
+        // Known sections with an invalid value are rejected in strict mode
+        assert_eq!(parse_strict("_e{5,4}:title|text|p:urgent").map_err(|e| e.kind), Err(ErrorKind::InvalidPriority));
+        assert_eq!(parse_strict("_e{5,4}:title|text|t:fatal").map_err(|e| e.kind), Err(ErrorKind::InvalidAlertType));
+        assert!(parse("_e{5,4}:title|text|p:urgent|t:fatal").is_ok());
+    }
+
+    #[test]
+    fn test_parse_invalid_header() {
+        assert_eq!(parse("_e{5,a}:title|text").map_err(|e| e.kind), Err(ErrorKind::InvalidEventHeader));
+        assert_eq!(parse("_e{5,4}title|text").map_err(|e| e.kind), Err(ErrorKind::InvalidEventHeader));
+        assert_eq!(parse("_e{4,4}:title|text").map_err(|e| e.kind), Err(ErrorKind::InvalidEventHeader));
+
+        // Lengths that overflow the position don't panic
+        assert_eq!(parse("_e{18446744073709551615,1}:a|b").map_err(|e| e.kind), Err(ErrorKind::InvalidEventHeader));
+        assert_eq!(parse("_e{1,18446744073709551615}:a|b").map_err(|e| e.kind), Err(ErrorKind::InvalidEventHeader));
+    }
+
+    #[test]
+    fn test_parse_duplicate_tags() {
+        let error = parse("_e{5,4}:title|text|#a|#b").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::DuplicateSection);
+        assert_eq!(error.token, "#b");
+    }
+
+    #[test]
+    fn test_parse_timestamp_not_float() {
+        assert_eq!(parse("_e{5,4}:title|text|d:now").map_err(|e| e.kind), Err(ErrorKind::TimestampNotFloat));
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        assert_eq!(parse("_e{5,10}:title|text").map_err(|e| e.kind), Err(ErrorKind::IncompleteInput));
+    }
+}
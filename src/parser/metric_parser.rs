@@ -1,131 +1,223 @@
-use {Message, Metric, Gauge, Counter, Timing, Histogram, Meter, Distribution, Set};
-use super::{Parser, ParseError};
+use {ParserOptions, MessageRef, MetricRef, SetRef, Cardinality, Gauge, GaugeOp, Counter, Timing, Histogram, Meter, Distribution};
+use super::{Parser, ParseError, ErrorKind, Field};
 
-pub trait MetricParser {
-    fn parse(self) -> Result<Message, ParseError>;
+pub trait MetricParser<'a> {
+    fn parse(self) -> Result<MessageRef<'a>, ParseError>;
 }
 
-impl MetricParser for Parser {
-    fn parse(mut self) -> Result<Message, ParseError> {
-        if self.chars.is_empty() {
-            return Err(ParseError::EmptyInput)
+impl<'a> MetricParser<'a> for Parser<'a> {
+    fn parse(mut self) -> Result<MessageRef<'a>, ParseError> {
+        if self.buf.is_empty() {
+            return Err(self.error(ErrorKind::EmptyInput, Field::Line, self.buf))
         }
 
+        self.check_trailing_separator()?;
+
         // Start with the name
-        let name = self.take_until(vec![':']);
+        let name = self.take_until(&[':']);
 
         if name.is_empty() {
-            return Err(ParseError::NoName)
+            return Err(self.error(ErrorKind::NoName, Field::Name, name))
         }
+        let name = self.check_length(name, self.options.max_name_length, ErrorKind::NameTooLong, Field::Name)?;
 
         // The value should be everything until the first pipe (`|`)
-        let value = match self.take_float_until(vec!['|']) {
-            Ok(v) => v,
-            Err(_) => return Err(ParseError::ValueNotFloat)
-        };
+        let value_string = self.take_until(&['|']);
 
         // The metric type should be everything until the next pipe, or the end
-        let metric_type = self.take_until(vec!['|']);
-
-        // The next part can either be the sample rate or tags,
-        // peek the value and match on `@` to get the sample rate
-        let sample_rate = match self.peek() {
-            Some('@') => {
-                self.skip(); // Skip the `@`
-                match self.take_float_until(vec!['|']) {
-                    Ok(v) => Some(v),
-                    Err(_) => return Err(ParseError::SampleRateNotFloat)
+        let metric_type = self.take_until(&['|']);
+
+        // Sets keep their value as a string, the values of all other metric types
+        // are floats. DogStatsD allows multiple values separated by a colon (`:`)
+        let mut values = Vec::new();
+        if metric_type != "s" {
+            for value in value_string.split(':') {
+                match value.parse() {
+                    Ok(v) => values.push(v),
+                    Err(_) => return Err(self.error(ErrorKind::ValueNotFloat, Field::Value, value))
                 }
             }
-            _ => None
-        };
+        }
 
-        // Peek the remaining string, if it starts with a pound (`#`)
-        // try and match tags
-        let tags = if Some('#') == self.peek() {
-            Some(self.parse_tags())
-        } else {
-            None
-        };
+        // Only timings, histograms and distributions can contain multiple values
+        if values.len() > 1 && ["c", "g", "m"].contains(&metric_type) {
+            return Err(self.error(ErrorKind::MultipleValuesNotAllowed, Field::Value, value_string))
+        }
 
-        let metric = match metric_type.as_ref() {
+        // The remaining sections can contain the sample rate, tags, a timestamp
+        // and origin detection fields in any order, sections that are not
+        // recognized are skipped unless we're in strict mode
+        let mut sample_rate = None;
+        let mut tags = None;
+        let mut timestamp = None;
+        let mut container_id = None;
+        let mut external_data = None;
+        let mut cardinality = None;
+        while let Some(c) = self.peek() {
+            let section = self.peek_until(&['|']);
+            match c {
+                '@' => {
+                    if sample_rate.is_some() {
+                        return Err(self.error(ErrorKind::DuplicateSection, Field::Section, section))
+                    }
+                    self.skip(); // Skip the `@`
+                    let token = self.peek_until(&['|']);
+                    sample_rate = match self.take_float_until(&['|']) {
+                        Ok(v) if v > 0.0 && v <= 1.0 => Some(v),
+                        Ok(_) => return Err(self.error(ErrorKind::InvalidSampleRate, Field::SampleRate, token)),
+                        Err(token) => return Err(self.error(ErrorKind::SampleRateNotFloat, Field::SampleRate, token))
+                    };
+                },
+                '#' => {
+                    if tags.is_some() {
+                        return Err(self.error(ErrorKind::DuplicateSection, Field::Section, section))
+                    }
+                    tags = Some(self.parse_tags()?);
+                },
+                // Timestamps start with a digit, so sections like
+                // `Tfoo` are handled as unknown sections
+                'T' if section[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                    if timestamp.is_some() {
+                        return Err(self.error(ErrorKind::DuplicateSection, Field::Section, section))
+                    }
+                    self.skip(); // Skip the `T`
+                    timestamp = match self.take_float_until(&['|']) {
+                        Ok(v) => Some(v),
+                        Err(token) => return Err(self.error(ErrorKind::TimestampNotFloat, Field::Timestamp, token))
+                    };
+                },
+                _ => {
+                    if self.skip_prefix("c:") {
+                        if container_id.is_some() {
+                            return Err(self.error(ErrorKind::DuplicateSection, Field::Section, section))
+                        }
+                        container_id = Some(self.take_until(&['|']));
+                    } else if self.skip_prefix("e:") {
+                        if external_data.is_some() {
+                            return Err(self.error(ErrorKind::DuplicateSection, Field::Section, section))
+                        }
+                        external_data = Some(self.take_until(&['|']));
+                    } else if self.skip_prefix("card:") {
+                        if cardinality.is_some() {
+                            return Err(self.error(ErrorKind::DuplicateSection, Field::Section, section))
+                        }
+                        // Keep the token, so a section with an invalid value still counts for duplicates
+                        let token = self.take_until(&['|']);
+                        if self.options.strict && token.parse::<Cardinality>().is_err() {
+                            return Err(self.error(ErrorKind::InvalidCardinality, Field::Section, token))
+                        }
+                        cardinality = Some(token);
+                    } else if self.options.strict {
+                        return Err(self.error(ErrorKind::UnknownSection, Field::Section, section))
+                    } else {
+                        self.take_until(&['|']);
+                    }
+                }
+            }
+        }
+
+        let metric = match metric_type {
             "ms" => {
-                Metric::Timing(Timing {
-                    value: value,
-                    sample_rate: sample_rate,
+                MetricRef::Timing(Timing {
+                    values,
+                    sample_rate,
                 })
             },
             "c" => {
-                Metric::Counter(Counter {
-                    value: value,
-                    sample_rate: sample_rate,
+                MetricRef::Counter(Counter {
+                    value: values[0],
+                    sample_rate,
                 })
             },
             "g" => {
-                Metric::Gauge(Gauge {
-                    value: value,
-                    sample_rate: sample_rate,
+                // A sign in front of the value changes the gauge
+                // relative to its current value
+                let op = match value_string.chars().next() {
+                    Some('+') => GaugeOp::INCREMENT,
+                    Some('-') => GaugeOp::DECREMENT,
+                    _ => GaugeOp::SET
+                };
+                MetricRef::Gauge(Gauge {
+                    value: values[0].abs(),
+                    op,
+                    sample_rate,
                 })
             },
             "m" => {
-                Metric::Meter(Meter {
-                    value: value,
-                    sample_rate: sample_rate,
+                MetricRef::Meter(Meter {
+                    value: values[0],
+                    sample_rate,
                 })
             },
             "h" => {
-                Metric::Histogram(Histogram {
-                    value: value,
-                    sample_rate: sample_rate,
+                MetricRef::Histogram(Histogram {
+                    values,
+                    sample_rate,
                 })
             },
             "d" => {
-                Metric::Distribution(Distribution {
-                    value: value,
-                    sample_rate: sample_rate,
+                MetricRef::Distribution(Distribution {
+                    values,
+                    sample_rate,
                 })
             },
             "s" => {
-                Metric::Set(Set {
-                    value: value,
-                    sample_rate: sample_rate,                        
+                if value_string.is_empty() {
+                    return Err(self.error(ErrorKind::NoValue, Field::Value, value_string))
+                }
+                MetricRef::Set(SetRef {
+                    value: value_string,
+                    sample_rate,
                 })
             }
-            _ => return Err(ParseError::UnknownMetricType)
+            _ => return Err(self.error(ErrorKind::UnknownMetricType, Field::MetricType, metric_type))
         };
 
-        Ok(Message {
-            name: name,
-            tags: tags,
-            metric: metric
+        Ok(MessageRef {
+            name,
+            tags,
+            timestamp,
+            container_id,
+            external_data,
+            cardinality: cardinality.and_then(|token| token.parse().ok()),
+            metric
         })
     }
 }
 
-pub fn parse(input: String) -> Result<Message, ParseError> {
-    Parser::new(input).parse()
+pub fn parse<'a>(input: &'a str, options: &ParserOptions) -> Result<MessageRef<'a>, ParseError> {
+    Parser::new(input, options.clone()).parse()
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use {ParserOptions, Message, Tags, Metric, Timing, Distribution, Gauge, GaugeOp, Set, ParseError, ErrorKind};
+
+    fn parse(input: &str) -> Result<Message, ParseError> {
+        super::parse(input, &ParserOptions::default()).map(|message| message.to_owned())
+    }
 
-    use super::parse;
-    use {Message, Metric, Timing};
+    fn parse_strict(input: &str) -> Result<Message, ParseError> {
+        super::parse(input, &ParserOptions { strict: true, ..Default::default() }).map(|message| message.to_owned())
+    }
 
     #[test]
     fn test_parse_with_tags() {
-        let result = parse("service.duration:101|ms|@0.9|#hostname:frontend1,namespace:web".to_string());
+        let result = parse("service.duration:101|ms|@0.9|#hostname:frontend1,namespace:web");
 
-        let mut tags = BTreeMap::new();
-        tags.insert("hostname".to_string(), "frontend1".to_string());
-        tags.insert("namespace".to_string(), "web".to_string());
+        let mut tags = Tags::new();
+        tags.push("hostname", "frontend1");
+        tags.push("namespace", "web");
 
         let expected = Message {
             name: "service.duration".to_string(),
             tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::Timing(Timing {
-                value: 101.0,
+                values: vec![101.0],
                 sample_rate: Some(0.9),
             })
         };
@@ -135,13 +227,86 @@ mod tests {
 
     #[test]
     fn test_parse_without_tags() {
-        let result = parse("service.duration:101|ms|@0.9|".to_string());
+        let result = parse("service.duration:101|ms|@0.9|");
 
         let expected = Message {
             name: "service.duration".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Timing(Timing {
+                values: vec![101.0],
+                sample_rate: Some(0.9),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_with_container_id() {
+        let result = parse("service.duration:101|ms|@0.9|#namespace:web|c:3ac6ec42|unknown");
+
+        let mut tags = Tags::new();
+        tags.push("namespace", "web");
+
+        let expected = Message {
+            name: "service.duration".to_string(),
+            tags: Some(tags),
+            timestamp: None,
+            container_id: Some("3ac6ec42".to_string()),
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Timing(Timing {
+                values: vec![101.0],
+                sample_rate: Some(0.9),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_with_timestamp() {
+        let result = parse("service.duration:101|ms|T1656581400|@0.9|#namespace:web");
+
+        let mut tags = Tags::new();
+        tags.push("namespace", "web");
+
+        let expected = Message {
+            name: "service.duration".to_string(),
+            tags: Some(tags),
+            timestamp: Some(1656581400f64),
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Timing(Timing {
+                values: vec![101.0],
+                sample_rate: Some(0.9),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_sections_in_any_order() {
+        let result = parse("service.duration:101|ms|c:3ac6ec42|unknown|#namespace:web|T1656581400|@0.9");
+
+        let mut tags = Tags::new();
+        tags.push("namespace", "web");
+
+        let expected = Message {
+            name: "service.duration".to_string(),
+            tags: Some(tags),
+            timestamp: Some(1656581400f64),
+            container_id: Some("3ac6ec42".to_string()),
+            external_data: None,
+            cardinality: None,
             metric: Metric::Timing(Timing {
-                value: 101.0,
+                values: vec![101.0],
                 sample_rate: Some(0.9),
             })
         };
@@ -149,9 +314,117 @@ mod tests {
         assert_eq!(result, Ok(expected));
     }
 
+    #[test]
+    fn test_parse_duplicate_sections() {
+        assert_eq!(parse("service.duration:101|ms|@0.9|@0.5").map_err(|e| e.kind), Err(ErrorKind::DuplicateSection));
+        assert_eq!(parse("service.duration:101|ms|#foo:bar|@0.5|#moo:maa").map_err(|e| e.kind), Err(ErrorKind::DuplicateSection));
+        assert_eq!(parse("service.duration:101|ms|T1656581400|T1656581401").map_err(|e| e.kind), Err(ErrorKind::DuplicateSection));
+        assert_eq!(parse("service.duration:101|ms|c:3ac6ec42|c:3ac6ec42").map_err(|e| e.kind), Err(ErrorKind::DuplicateSection));
+        assert_eq!(parse("service.duration:101|ms|card:bogus|card:high").map_err(|e| e.kind), Err(ErrorKind::DuplicateSection));
+    }
+
+    #[test]
+    fn test_parse_timestamp_not_float() {
+        assert_eq!(parse("service.duration:101|ms|T1656581400s").map_err(|e| e.kind), Err(ErrorKind::TimestampNotFloat));
+
+        // Sections that don't start with a digit after the `T` are unknown sections
+        assert_eq!(parse("service.duration:101|ms|Tnow").map(|m| m.timestamp), Ok(None));
+        assert_eq!(parse_strict("service.duration:101|ms|Tnow").map_err(|e| e.kind), Err(ErrorKind::UnknownSection));
+    }
+
+    #[test]
+    fn test_parse_multiple_values() {
+        let result = parse("latency:12:15:9|d|@0.5");
+
+        let expected = Message {
+            name: "latency".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Distribution(Distribution {
+                values: vec![12.0, 15.0, 9.0],
+                sample_rate: Some(0.5),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_multiple_values_not_allowed() {
+        assert_eq!(parse("requests:1:2|c").map_err(|e| e.kind), Err(ErrorKind::MultipleValuesNotAllowed));
+        assert_eq!(parse("latency:12:aaa|h").map_err(|e| e.kind), Err(ErrorKind::ValueNotFloat));
+    }
+
+    #[test]
+    fn test_parse_gauge_operations() {
+        let gauge = |op, value| {
+            Ok(Message {
+                name: "connections".to_string(),
+                tags: None,
+                timestamp: None,
+                container_id: None,
+                external_data: None,
+                cardinality: None,
+                metric: Metric::Gauge(Gauge {
+                    value,
+                    op,
+                    sample_rate: None,
+                })
+            })
+        };
+
+        assert_eq!(parse("connections:5|g"), gauge(GaugeOp::SET, 5.0));
+        assert_eq!(parse("connections:+5|g"), gauge(GaugeOp::INCREMENT, 5.0));
+        assert_eq!(parse("connections:-3|g"), gauge(GaugeOp::DECREMENT, 3.0));
+    }
+
+    #[test]
+    fn test_parse_set() {
+        let result = parse("users.unique:alice@example.com|s|@0.5");
+
+        let expected = Message {
+            name: "users.unique".to_string(),
+            tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: Metric::Set(Set {
+                value: "alice@example.com".to_string(),
+                sample_rate: Some(0.5),
+            })
+        };
+
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_set_without_value() {
+        assert_eq!(parse("users.unique:|s").map_err(|e| e.kind), Err(ErrorKind::NoValue));
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        let error = parse_strict("service.duration:101|ms|@0.9|junk|#namespace:web").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownSection);
+        assert_eq!(error.token, "junk");
+
+        assert_eq!(parse_strict("service.duration:101|ms||@0.9").map_err(|e| e.kind), Err(ErrorKind::UnknownSection));
+        assert_eq!(parse_strict("service.duration:101|ms|").map_err(|e| e.kind), Err(ErrorKind::TrailingSeparator));
+        assert_eq!(parse_strict("service.duration:101|ms|@0.9|").map_err(|e| e.kind), Err(ErrorKind::TrailingSeparator));
+        assert!(parse_strict("service.duration:101|ms|@0.9|#namespace:web|T1656581400|c:3ac6ec42").is_ok());
+
+        // Known sections with an invalid value are rejected in strict mode
+        assert_eq!(parse_strict("service.duration:101|ms|card:bogus").map_err(|e| e.kind), Err(ErrorKind::InvalidCardinality));
+        assert_eq!(parse("service.duration:101|ms|card:bogus").map(|m| m.cardinality), Ok(None));
+    }
+
     #[test]
     fn test_parse_invalid() {
-        let result = parse("service.duration:101|aaa|@0.9|".to_string());
+        let result = parse("service.duration:101|aaa|@0.9|");
         assert!(result.is_err());
     }
 }
@@ -1,41 +1,164 @@
-use std::{error,fmt};
-use std::num::ParseFloatError;
-use std::vec::Vec;
-use std::collections::BTreeMap;
+use std::{cmp,error,fmt};
+use {ParserOptions, TagRef};
 
 
 pub mod metric_parser;
 pub mod service_check_parser;
+pub mod event_parser;
 
-#[derive(Debug,PartialEq)]
-pub enum ParseError {
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ErrorKind {
     /// No content in statsd message
     EmptyInput,
     /// Incomplete input in statsd message
     IncompleteInput,
     /// No name in input
     NoName,
+    /// No value in input
+    NoValue,
     /// Value is not a float
     ValueNotFloat,
     /// Sample rate is not a float
     SampleRateNotFloat,
+    /// Sample rate is not greater than 0 and at most 1
+    InvalidSampleRate,
     /// Metric type is unknown
     UnknownMetricType,
+    /// Event header is malformed or does not match the payload
+    InvalidEventHeader,
+    /// Multiple values for a metric type that only allows one
+    MultipleValuesNotAllowed,
+    /// Timestamp is not a float
+    TimestampNotFloat,
+    /// The same section occurs more than once
+    DuplicateSection,
+    /// Input is not valid UTF-8
+    InvalidUtf8,
+    /// Section is not recognized, only in strict mode
+    UnknownSection,
+    /// Input ends with a separator, only in strict mode
+    TrailingSeparator,
+    /// Service check status is invalid, only in strict mode
+    InvalidStatus,
+    /// Cardinality is invalid, only in strict mode
+    InvalidCardinality,
+    /// Event priority is invalid, only in strict mode
+    InvalidPriority,
+    /// Event alert type is invalid, only in strict mode
+    InvalidAlertType,
+    /// Name is longer than the configured limit
+    NameTooLong,
+    /// More tags than the configured limit
+    TooManyTags,
+    /// Tag key or value is longer than the configured limit
+    TagTooLong,
 }
 
-impl fmt::Display for ParseError {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ParseError::EmptyInput => write!(f, "Empty input"),
-            ParseError::IncompleteInput => write!(f, "Incomplete input"),
-            ParseError::NoName => write!(f, "No name in input"),
-            ParseError::ValueNotFloat => write!(f, "Value is not a float"),
-            ParseError::SampleRateNotFloat => write!(f, "Sample rate is not a float"),
-            ParseError::UnknownMetricType => write!(f, "Unknown metric type")
+            ErrorKind::EmptyInput => write!(f, "Empty input"),
+            ErrorKind::IncompleteInput => write!(f, "Incomplete input"),
+            ErrorKind::NoName => write!(f, "No name in input"),
+            ErrorKind::NoValue => write!(f, "No value in input"),
+            ErrorKind::ValueNotFloat => write!(f, "Value is not a float"),
+            ErrorKind::SampleRateNotFloat => write!(f, "Sample rate is not a float"),
+            ErrorKind::InvalidSampleRate => write!(f, "Sample rate is not between 0 and 1"),
+            ErrorKind::UnknownMetricType => write!(f, "Unknown metric type"),
+            ErrorKind::InvalidEventHeader => write!(f, "Invalid event header"),
+            ErrorKind::MultipleValuesNotAllowed => write!(f, "Multiple values not allowed for metric type"),
+            ErrorKind::TimestampNotFloat => write!(f, "Timestamp is not a float"),
+            ErrorKind::DuplicateSection => write!(f, "Duplicate section"),
+            ErrorKind::InvalidUtf8 => write!(f, "Input is not valid UTF-8"),
+            ErrorKind::UnknownSection => write!(f, "Unknown section"),
+            ErrorKind::TrailingSeparator => write!(f, "Trailing separator"),
+            ErrorKind::InvalidStatus => write!(f, "Invalid service check status"),
+            ErrorKind::InvalidCardinality => write!(f, "Invalid cardinality"),
+            ErrorKind::InvalidPriority => write!(f, "Invalid event priority"),
+            ErrorKind::InvalidAlertType => write!(f, "Invalid event alert type"),
+            ErrorKind::NameTooLong => write!(f, "Name is too long"),
+            ErrorKind::TooManyTags => write!(f, "Too many tags"),
+            ErrorKind::TagTooLong => write!(f, "Tag is too long")
         }
     }
 }
 
+/// The field that was being parsed when an error occurred
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Field {
+    /// The line as a whole
+    Line,
+    Name,
+    Value,
+    MetricType,
+    SampleRate,
+    Timestamp,
+    Tags,
+    /// One of the optional sections after the metric type
+    Section,
+    EventHeader,
+    EventTitle,
+    EventText,
+    Status,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Field::Line => write!(f, "line"),
+            Field::Name => write!(f, "name"),
+            Field::Value => write!(f, "value"),
+            Field::MetricType => write!(f, "metric type"),
+            Field::SampleRate => write!(f, "sample rate"),
+            Field::Timestamp => write!(f, "timestamp"),
+            Field::Tags => write!(f, "tags"),
+            Field::Section => write!(f, "section"),
+            Field::EventHeader => write!(f, "event header"),
+            Field::EventTitle => write!(f, "event title"),
+            Field::EventText => write!(f, "event text"),
+            Field::Status => write!(f, "status")
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ParseError {
+    pub kind: ErrorKind,
+    /// The field that was being parsed
+    pub field: Field,
+    /// Byte offset of the offending token in the line
+    pub position: usize,
+    /// The offending token
+    pub token: String,
+    /// The line that was being parsed
+    pub line: String,
+}
+
+impl fmt::Display for ParseError {
+    /// Renders the error with the line and a caret underline of the token:
+    ///
+    /// ```text
+    /// Value is not a float while parsing value at position 7
+    /// gorets:aaa|h
+    ///        ^^^
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} while parsing {} at position {}", self.kind, self.field, self.position)?;
+        writeln!(f, "{}", self.line)?;
+        let indent = match self.line.get(..self.position) {
+            Some(before) => before.chars().count(),
+            None => 0
+        };
+        let width = cmp::max(1, self.token.chars().count());
+        write!(f, "{}{}", " ".repeat(indent), "^".repeat(width))
+    }
+}
+
 impl error::Error for ParseError {
   // Implement description so that older versions of rust still work
   fn description(&self) -> &str {
@@ -44,75 +167,162 @@ impl error::Error for ParseError {
 }
 
 #[derive(Debug,PartialEq)]
-pub struct Parser {
-    chars: Vec<char>,
-    len: usize,
-    pos: usize
+pub struct Parser<'a> {
+    buf: &'a str,
+    pos: usize,
+    options: ParserOptions
 }
 
-impl Parser {
-    // Returns a Parser for given string
-    pub fn new(buf: String) -> Parser {
-        let chars: Vec<char> = buf.trim_end().chars().collect();
-        let len = chars.len();
+impl<'a> Parser<'a> {
+    // Returns a Parser for given string and options
+    pub fn new(buf: &'a str, options: ParserOptions) -> Parser<'a> {
         Parser {
-            chars: chars,
-            len:   len,
-            pos:   0
+            buf: buf.trim_end(),
+            pos: 0,
+            options
         }
     }
 
+    /// Returns a Parser that only removes the line ending, for input
+    /// where trailing whitespace can be part of a field
+    pub fn new_untrimmed(buf: &'a str, options: ParserOptions) -> Parser<'a> {
+        Parser {
+            buf: buf.trim_end_matches(&['\r', '\n'][..]),
+            pos: 0,
+            options
+        }
+    }
+
+    /// Removes the trailing whitespace after the current position
+    fn trim_end(&mut self) {
+        let end = self.pos + self.rest().trim_end().len();
+        self.buf = &self.buf[..end];
+    }
+
     /// Consumes the buffer until the given character is found
     /// or the end is reached
-    fn take_until(&mut self, to_match: Vec<char>) -> String {
-        let mut chars = Vec::new();
-        loop {
-            if self.pos >= self.len {
-                break
-            }
-            let current_char = self.chars[self.pos];
-            self.pos += 1;
+    fn take_until(&mut self, to_match: &[char]) -> &'a str {
+        let rest = &self.buf[self.pos..];
+        for (i, current_char) in rest.char_indices() {
             if to_match.contains(&current_char) {
-                break
-            } else {
-                chars.push(current_char);
+                self.pos += i + current_char.len_utf8();
+                return &rest[..i]
             }
         }
-        chars.into_iter().collect()
+        self.pos = self.buf.len();
+        rest
     }
 
     /// Consumes the buffer untill the character is found
-    /// or the end is reached, the result is parsed into a float
-    fn take_float_until(&mut self, to_match: Vec<char>) -> Result<f64, ParseFloatError> {
+    /// or the end is reached, the result is parsed into a float.
+    /// Returns the consumed string if it's not a float
+    fn take_float_until(&mut self, to_match: &[char]) -> Result<f64, &'a str> {
         let string = self.take_until(to_match);
-        string.parse()
+        string.parse().map_err(|_| string)
     }
 
-    /// Returns the current character in the buffer
-    fn peek(&mut self) -> Option<char> {
-        if self.pos == self.len {
-            None
+    /// Returns the buffer until the given character is found
+    /// or the end is reached, without consuming it
+    fn peek_until(&self, to_match: &[char]) -> &'a str {
+        let rest = self.rest();
+        match rest.find(|c| to_match.contains(&c)) {
+            Some(i) => &rest[..i],
+            None => rest
+        }
+    }
+
+    /// Returns the remaining buffer, without consuming it
+    fn rest(&self) -> &'a str {
+        &self.buf[self.pos..]
+    }
+
+    /// Consumes the given number of UTF-8 bytes from the buffer
+    fn take_bytes(&mut self, length: usize) -> Result<&'a str, ErrorKind> {
+        let end = match self.pos.checked_add(length) {
+            Some(end) => end,
+            // The length can't be part of the buffer
+            None => return Err(ErrorKind::InvalidEventHeader)
+        };
+        if end > self.buf.len() {
+            return Err(ErrorKind::IncompleteInput)
+        }
+        if !self.buf.is_char_boundary(end) {
+            // The length ends halfway through a character
+            return Err(ErrorKind::InvalidEventHeader)
+        }
+        let string = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(string)
+    }
+
+    /// Consumes the prefix if the buffer continues with it,
+    /// returns whether the prefix was found
+    fn skip_prefix(&mut self, prefix: &str) -> bool {
+        if self.buf[self.pos..].starts_with(prefix) {
+            self.pos += prefix.len();
+            true
         } else {
-            Some(self.chars[self.pos])
+            false
         }
     }
 
+    /// Returns the current character in the buffer
+    fn peek(&mut self) -> Option<char> {
+        self.buf[self.pos..].chars().next()
+    }
+
     /// Returns the previous character in the buffer
     fn last(&mut self) -> Option<char> {
-        if self.pos == 0 {
-            None
-        } else {
-            Some(self.chars[self.pos - 1 ])
-        }
+        self.buf[..self.pos].chars().next_back()
     }
 
     /// Moves the buffer to the next position
     fn skip(&mut self) {
-        self.pos += 1;
+        if let Some(current_char) = self.peek() {
+            self.pos += current_char.len_utf8();
+        }
+    }
+
+    /// Returns an error in strict mode if the buffer ends with a separator (`|`)
+    fn check_trailing_separator(&self) -> Result<(), ParseError> {
+        if self.options.strict && self.buf.ends_with('|') {
+            let separator = &self.buf[self.buf.len() - 1..];
+            return Err(self.error(ErrorKind::TrailingSeparator, Field::Section, separator))
+        }
+        Ok(())
+    }
+
+    /// Checks the length of the token against the limit, returns the token truncated
+    /// to the limit if truncating is enabled in the options or an error otherwise
+    fn check_length(&self, token: &'a str, limit: Option<usize>, kind: ErrorKind, field: Field) -> Result<&'a str, ParseError> {
+        match limit {
+            Some(limit) if token.len() > limit => {
+                if !self.options.truncate {
+                    return Err(self.error(kind, field, token))
+                }
+                let mut end = limit;
+                while !token.is_char_boundary(end) {
+                    end -= 1;
+                }
+                Ok(&token[..end])
+            },
+            _ => Ok(token)
+        }
     }
 
-    fn parse_tags(&mut self) -> BTreeMap<String, String> {
-        let mut tags = BTreeMap::new();
+    /// Returns an error for the given token, which should be a slice of the buffer
+    fn error(&self, kind: ErrorKind, field: Field, token: &str) -> ParseError {
+        ParseError {
+            kind,
+            field,
+            position: token.as_ptr() as usize - self.buf.as_ptr() as usize,
+            token: token.to_owned(),
+            line: self.buf.to_owned()
+        }
+    }
+
+    fn parse_tags(&mut self) -> Result<Vec<TagRef<'a>>, ParseError> {
+        let mut tags = Vec::new();
 
         self.skip(); // Skip the `#`
 
@@ -126,70 +336,150 @@ impl Parser {
             }
 
             // Stop the loop if we have nothing left to parse
-            let tag = self.take_until(vec![',', '|']);
+            let tag = self.take_until(&[',', '|']);
             if tag.is_empty() {
                 break
             }
 
-            // Split the string on ':' and use the first part as key, last parts as value
+            // Split the string on the first ':' and use the first part as key, the rest as value
             // host:localhost:3000 will become key: host, value: localhost:3000
-            let mut split= tag.split(":");
-            match split.next() {
-                Some(key) => {
-                  let parts: Vec<&str> = split.collect();
-                  tags.insert(key.to_owned(), parts.join(":"))
-                },
-                None => break
+            // foo: will become key: foo, value: empty, foo will become key: foo without a value
+            let (key, value) = match tag.find(':') {
+                Some(i) => (&tag[..i], Some(&tag[i + 1..])),
+                None => (tag, None)
+            };
+
+            // Skip or reject tags when the limit is reached
+            if let Some(max_tags) = self.options.max_tags {
+                if tags.len() >= max_tags {
+                    if self.options.truncate {
+                        continue
+                    }
+                    return Err(self.error(ErrorKind::TooManyTags, Field::Tags, tag))
+                }
+            }
+
+            let key = self.check_length(key, self.options.max_tag_length, ErrorKind::TagTooLong, Field::Tags)?;
+            let value = match value {
+                Some(value) => Some(self.check_length(value, self.options.max_tag_length, ErrorKind::TagTooLong, Field::Tags)?),
+                None => None
             };
+            tags.push(TagRef { key, value });
         }
 
-        tags
+        Ok(tags)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
-    use super::Parser;
+    use super::{Parser, ParseError, ErrorKind, Field};
+    use {ParserOptions, TagRef};
 
     #[test]
     fn test_take_until() {
-        let mut parser = Parser::new("this is a string".to_string());
+        let mut parser = Parser::new("this is a string", ParserOptions::default());
 
         // Returns up untill the first occurrence of the character
-        assert_eq!(parser.take_until(vec![' ']), "this");
+        assert_eq!(parser.take_until(&[' ']), "this");
 
         // Moves the position to the first occurrence
         assert_eq!(parser.pos, 5);
 
         // Returns the rest of the string if character is not found
-        assert_eq!(parser.take_until(vec!['.']), "is a string");
+        assert_eq!(parser.take_until(&['.']), "is a string");
 
         // Moves the position to the end of the string
         assert_eq!(parser.pos, 16);
     }
 
+    #[test]
+    fn test_trim_end() {
+        let mut parser = Parser::new_untrimmed("text  |h:a  \r\n", ParserOptions::default());
+
+        // Only removes the line ending
+        assert_eq!(parser.buf, "text  |h:a  ");
+
+        // Keeps whitespace before the position
+        parser.pos = 6;
+        parser.trim_end();
+        assert_eq!(parser.buf, "text  |h:a");
+
+        parser.pos = 10;
+        parser.trim_end();
+        assert_eq!(parser.buf, "text  |h:a");
+    }
+
     #[test]
     fn test_take_float_until() {
-        let mut parser = Parser::new("10.01|number|string".to_string());
+        let mut parser = Parser::new("10.01|number|string", ParserOptions::default());
 
         // Returns float up untill the first occurrence of the character
-        assert_eq!(parser.take_float_until(vec!['|']), Ok(10.01));
+        assert_eq!(parser.take_float_until(&['|']), Ok(10.01));
 
         // Moves the position to the first occurrence
         assert_eq!(parser.pos, 6);
 
         // Returns err if not float
-        assert!(parser.take_float_until(vec!['|']).is_err());
+        assert!(parser.take_float_until(&['|']).is_err());
 
         // Moves the position to the end of the string
         assert_eq!(parser.pos, 13);
     }
 
+    #[test]
+    fn test_take_bytes() {
+        let mut parser = Parser::new("aβc|d", ParserOptions::default());
+
+        // Returns the characters that fit in the given number of bytes
+        assert_eq!(parser.take_bytes(3), Ok("aβ"));
+
+        // Moves the position by the number of bytes taken
+        assert_eq!(parser.pos, 3);
+
+        // Returns err if the input is too short
+        assert_eq!(parser.take_bytes(10), Err(ErrorKind::IncompleteInput));
+
+        // Returns err if the length overflows the position
+        assert_eq!(parser.take_bytes(usize::MAX), Err(ErrorKind::InvalidEventHeader));
+
+        // Returns err if the length ends halfway through a character
+        let mut parser = Parser::new("βc", ParserOptions::default());
+        assert_eq!(parser.take_bytes(1), Err(ErrorKind::InvalidEventHeader));
+    }
+
+    #[test]
+    fn test_skip_prefix() {
+        let mut parser = Parser::new("card:high", ParserOptions::default());
+
+        // Returns false and does not move the position if the prefix is not found
+        assert!(!parser.skip_prefix("c:"));
+        assert_eq!(parser.pos, 0);
+
+        // Returns true and moves the position past the prefix
+        assert!(parser.skip_prefix("card:"));
+        assert_eq!(parser.pos, 5);
+
+        // Returns false if the prefix is longer than the buffer
+        assert!(!parser.skip_prefix("highest"));
+        assert_eq!(parser.pos, 5);
+    }
+
+    #[test]
+    fn test_peek_until() {
+        let mut parser = Parser::new("foo|bar", ParserOptions::default());
+        parser.pos = 1;
+
+        // Returns up untill the first occurrence of the character
+        assert_eq!(parser.peek_until(&['|']), "oo");
+
+        // It does not move the position
+        assert_eq!(parser.pos, 1);
+    }
+
     #[test]
     fn test_peek() {
-        let mut parser = Parser::new("this is a string".to_string());
+        let mut parser = Parser::new("this is a string", ParserOptions::default());
         parser.pos = 10;
 
         // Returns the character at the current position
@@ -206,7 +496,7 @@ mod tests {
 
     #[test]
     fn test_last() {
-        let mut parser = Parser::new("abcdef".to_string());
+        let mut parser = Parser::new("abcdef", ParserOptions::default());
         parser.pos = 0;
 
         // Returns None if we're at the beginning
@@ -223,7 +513,7 @@ mod tests {
 
     #[test]
     fn test_skip() {
-        let mut parser = Parser::new("foo#bar".to_string());
+        let mut parser = Parser::new("foo#bar", ParserOptions::default());
         parser.pos = 3;
         parser.skip();
 
@@ -231,16 +521,133 @@ mod tests {
         assert_eq!(parser.pos, 4);
     }
 
+    #[test]
+    fn test_error() {
+        let mut parser = Parser::new("gorets:aaa|h", ParserOptions::default());
+        parser.take_until(&[':']);
+        let token = parser.take_until(&['|']);
+
+        let expected = ParseError {
+            kind: ErrorKind::ValueNotFloat,
+            field: Field::Value,
+            position: 7,
+            token: "aaa".to_string(),
+            line: "gorets:aaa|h".to_string(),
+        };
+
+        // Uses the offset of the token in the buffer as position
+        assert_eq!(parser.error(ErrorKind::ValueNotFloat, Field::Value, token), expected);
+
+        // Renders the line with the token underlined
+        assert_eq!(
+            expected.to_string(),
+            "Value is not a float while parsing value at position 7\ngorets:aaa|h\n       ^^^"
+        );
+    }
+
+    #[test]
+    fn test_check_trailing_separator() {
+        let strict = ParserOptions { strict: true, ..Default::default() };
+
+        // Returns an error pointing at the separator in strict mode
+        let parser = Parser::new("foo|", strict.clone());
+        let error = parser.check_trailing_separator().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TrailingSeparator);
+        assert_eq!(error.position, 3);
+
+        // Returns ok without a trailing separator
+        let parser = Parser::new("foo", strict);
+        assert!(parser.check_trailing_separator().is_ok());
+
+        // Returns ok in lenient mode
+        let parser = Parser::new("foo|", ParserOptions::default());
+        assert!(parser.check_trailing_separator().is_ok());
+    }
+
     #[test]
     fn test_parse_tags() {
-        let mut parser = Parser::new("#hostname:frontend1,redis_instance:10.0.0.16:6379,namespace:web".to_string());
+        let mut parser = Parser::new("#hostname:frontend1,redis_instance:10.0.0.16:6379,namespace:web", ParserOptions::default());
 
-        let mut tags = BTreeMap::new();
-        tags.insert("hostname".to_string(), "frontend1".to_string());
-        tags.insert("redis_instance".to_string(), "10.0.0.16:6379".to_string());
-        tags.insert("namespace".to_string(), "web".to_string());
+        let tags = vec![
+            TagRef { key: "hostname", value: Some("frontend1") },
+            TagRef { key: "redis_instance", value: Some("10.0.0.16:6379") },
+            TagRef { key: "namespace", value: Some("web") },
+        ];
 
         // Increases the position by one
-        assert_eq!(parser.parse_tags(), tags);
+        assert_eq!(parser.parse_tags(), Ok(tags));
+    }
+
+    #[test]
+    fn test_parse_tags_duplicate_keys() {
+        let mut parser = Parser::new("#team:web,env:production,team:ops", ParserOptions::default());
+
+        let tags = vec![
+            TagRef { key: "team", value: Some("web") },
+            TagRef { key: "env", value: Some("production") },
+            TagRef { key: "team", value: Some("ops") },
+        ];
+
+        // Keeps the order and duplicate keys
+        assert_eq!(parser.parse_tags(), Ok(tags));
+    }
+
+    #[test]
+    fn test_parse_tags_without_value() {
+        let mut parser = Parser::new("#foo,moo:,host:localhost:3000", ParserOptions::default());
+
+        let tags = vec![
+            TagRef { key: "foo", value: None },
+            TagRef { key: "moo", value: Some("") },
+            TagRef { key: "host", value: Some("localhost:3000") },
+        ];
+
+        // Distinguishes tags without a value from tags with an empty value
+        assert_eq!(parser.parse_tags(), Ok(tags));
+    }
+
+    #[test]
+    fn test_parse_tags_with_limits() {
+        let options = ParserOptions {
+            max_tags: Some(2),
+            max_tag_length: Some(5),
+            ..Default::default()
+        };
+
+        // Returns err if there are too many tags
+        let mut parser = Parser::new("#a:1,b:2,c:3", options.clone());
+        assert_eq!(parser.parse_tags().map_err(|e| e.kind), Err(ErrorKind::TooManyTags));
+
+        // Returns err if a tag is too long
+        let mut parser = Parser::new("#a:123456", options.clone());
+        assert_eq!(parser.parse_tags().map_err(|e| e.kind), Err(ErrorKind::TagTooLong));
+
+        // Skips and truncates tags if truncating is enabled
+        let mut parser = Parser::new("#a:123456,b:2,c:3", ParserOptions { truncate: true, ..options });
+
+        let tags = vec![
+            TagRef { key: "a", value: Some("12345") },
+            TagRef { key: "b", value: Some("2") },
+        ];
+
+        assert_eq!(parser.parse_tags(), Ok(tags));
+    }
+
+    #[test]
+    fn test_check_length() {
+        let parser = Parser::new("goretsβ", ParserOptions::default());
+
+        // Returns the token if there is no limit or it's within the limit
+        assert_eq!(parser.check_length(parser.buf, None, ErrorKind::NameTooLong, Field::Name), Ok("goretsβ"));
+        assert_eq!(parser.check_length(parser.buf, Some(8), ErrorKind::NameTooLong, Field::Name), Ok("goretsβ"));
+
+        // Returns err if the token is too long
+        let error = parser.check_length(parser.buf, Some(7), ErrorKind::NameTooLong, Field::Name).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NameTooLong);
+        assert_eq!(error.token, "goretsβ");
+
+        // Truncates on a character boundary if truncating is enabled
+        let parser = Parser::new("goretsβ", ParserOptions { truncate: true, ..Default::default() });
+        assert_eq!(parser.check_length(parser.buf, Some(7), ErrorKind::NameTooLong, Field::Name), Ok("gorets"));
     }
 }
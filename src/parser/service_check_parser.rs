@@ -1,40 +1,44 @@
-use {Message, Metric, ServiceCheck, Status};
-use super::{Parser, ParseError};
+use {ParserOptions, MessageRef, MetricRef, ServiceCheckRef, Status};
+use super::{Parser, ParseError, ErrorKind, Field};
 
-pub trait ServiceStatusParser {
-    fn parse(self) -> Result<Message, ParseError>;
+pub trait ServiceStatusParser<'a> {
+    fn parse(self) -> Result<MessageRef<'a>, ParseError>;
 }
 
-impl ServiceStatusParser for Parser {
-    fn parse(mut self) -> Result<Message, ParseError> {
-        if self.chars.is_empty() {
-            return Err(ParseError::EmptyInput)
+impl<'a> ServiceStatusParser<'a> for Parser<'a> {
+    fn parse(mut self) -> Result<MessageRef<'a>, ParseError> {
+        if self.buf.is_empty() {
+            return Err(self.error(ErrorKind::EmptyInput, Field::Line, self.buf))
         }
 
+        self.check_trailing_separator()?;
+
         // Start with the service check tag
-        self.take_until(vec!['|']);
+        self.take_until(&['|']);
 
         // Get the name
-        let name = self.take_until(vec!['|']);
+        let name = self.take_until(&['|']);
         if name.is_empty() {
-            return Err(ParseError::NoName)
+            return Err(self.error(ErrorKind::NoName, Field::Name, name))
         }
-
-        // Get the status
-        let status = match self.take_until(vec!['|']).as_ref() {
-            "0" => Status::OK,
-            "1" => Status::WARNING,
-            "2" => Status::CRITICAL,
-            _ => Status::UNKNOWN
+        let name = self.check_length(name, self.options.max_name_length, ErrorKind::NameTooLong, Field::Name)?;
+
+        // Get the status, in strict mode only the statuses
+        // from the protocol are accepted
+        let status = self.take_until(&['|']);
+        let status = match status.parse() {
+            Ok(status) => status,
+            Err(_) if self.options.strict => return Err(self.error(ErrorKind::InvalidStatus, Field::Status, status)),
+            Err(_) => Status::UNKNOWN
         };
 
         // Peek the string to see if we need to parse a timestamp
         let timestamp = if Some('d') == self.peek() {
             self.skip();
             self.skip();
-            match self.take_float_until(vec!['|']) {
+            match self.take_float_until(&['|']) {
                 Ok(v) => Some(v),
-                Err(_) => return Err(ParseError::ValueNotFloat)
+                Err(token) => return Err(self.error(ErrorKind::TimestampNotFloat, Field::Timestamp, token))
             }
         } else {
             None
@@ -44,14 +48,14 @@ impl ServiceStatusParser for Parser {
         let hostname = if Some('h') == self.peek() {
             self.skip();
             self.skip();
-            Some(self.take_until(vec!['|']))
+            Some(self.take_until(&['|']))
         } else {
             None
         };
 
         // Peek the string to see if we need to parse tags
         let tags = if Some('#') == self.peek() {
-            Some(self.parse_tags())
+            Some(self.parse_tags()?)
         } else {
             None
         };
@@ -60,47 +64,65 @@ impl ServiceStatusParser for Parser {
         let message = if Some('m') == self.peek() {
             self.skip();
             self.skip();
-            Some(self.take_until(vec!['|']))
+            Some(self.take_until(&['|']))
         } else {
             None
         };
 
-        let service_check = ServiceCheck {
-            status: status,
-            timestamp: timestamp,
-            hostname: hostname,
-            message: message
+        // Anything that's left is not recognized
+        if self.options.strict && self.peek().is_some() {
+            return Err(self.error(ErrorKind::UnknownSection, Field::Section, self.peek_until(&['|'])))
+        }
+
+        let service_check = ServiceCheckRef {
+            status,
+            timestamp,
+            hostname,
+            message
         };
 
-        Ok(Message {
-            name: name,
-            tags: tags,
-            metric: Metric::ServiceCheck(service_check)
+        Ok(MessageRef {
+            name,
+            tags,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            metric: MetricRef::ServiceCheck(service_check)
         })
     }
 }
 
-pub fn parse(input: String) -> Result<Message, ParseError> {
-    Parser::new(input).parse()
+pub fn parse<'a>(input: &'a str, options: &ParserOptions) -> Result<MessageRef<'a>, ParseError> {
+    Parser::new(input, options.clone()).parse()
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use {ParserOptions, Message, Tags, Metric, ServiceCheck, Status, ParseError, ErrorKind};
 
-    use super::parse;
-    use {Message, Metric, ServiceCheck, Status};
+    fn parse(input: &str) -> Result<Message, ParseError> {
+        super::parse(input, &ParserOptions::default()).map(|message| message.to_owned())
+    }
+
+    fn parse_strict(input: &str) -> Result<Message, ParseError> {
+        super::parse(input, &ParserOptions { strict: true, ..Default::default() }).map(|message| message.to_owned())
+    }
 
     #[test]
     fn test_parse_with_tags() {
-        let result = parse("_sc|Redis connection|2|d:10101|h:frontend1|#redis_instance:10.0.0.16:6379|m:Redis connection timed out after 10s".to_string());
+        let result = parse("_sc|Redis connection|2|d:10101|h:frontend1|#redis_instance:10.0.0.16:6379|m:Redis connection timed out after 10s");
 
-        let mut tags = BTreeMap::new();
-        tags.insert("redis_instance".to_string(), "10.0.0.16:6379".to_string());
+        let mut tags = Tags::new();
+        tags.push("redis_instance", "10.0.0.16:6379");
 
         let expected = Message {
             name: "Redis connection".to_string(),
             tags: Some(tags),
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::ServiceCheck(ServiceCheck {
                 status: Status::CRITICAL,
                 timestamp: Some(10101f64),
@@ -114,11 +136,15 @@ mod tests {
 
     #[test]
     fn test_parse_without_tags() {
-        let result = parse("_sc|Redis connection|0|d:10101|h:frontend1|m:Redis connection timed out after 10s".to_string());
+        let result = parse("_sc|Redis connection|0|d:10101|h:frontend1|m:Redis connection timed out after 10s");
 
         let expected = Message {
             name: "Redis connection".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::ServiceCheck(ServiceCheck {
                 status: Status::OK,
                 timestamp: Some(10101f64),
@@ -132,11 +158,15 @@ mod tests {
 
     #[test]
     fn test_parse_without_duration() {
-        let result = parse("_sc|Redis connection|1|h:frontend1|m:Redis connection timed out after 10s".to_string());
+        let result = parse("_sc|Redis connection|1|h:frontend1|m:Redis connection timed out after 10s");
 
         let expected = Message {
             name: "Redis connection".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric: Metric::ServiceCheck(ServiceCheck {
                 status: Status::WARNING,
                 timestamp: None,
@@ -150,11 +180,15 @@ mod tests {
 
     #[test]
     fn test_parse_minimum_required() {
-        let result = parse("_sc|Redis connection".to_string());
+        let result = parse("_sc|Redis connection");
 
         let expected = Message {
             name: "Redis connection".to_string(),
             tags: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
             metric:  Metric::ServiceCheck(ServiceCheck {
                 status: Status::UNKNOWN,
                 timestamp: None,
@@ -166,10 +200,24 @@ mod tests {
         assert_eq!(result, Ok(expected));
     }
 
+    #[test]
+    fn test_parse_strict() {
+        assert!(parse_strict("_sc|Redis connection|3|d:10101|h:frontend1|#redis_instance:10.0.0.16:6379|m:Redis connection timed out after 10s").is_ok());
+        assert_eq!(parse_strict("_sc|Redis connection").map_err(|e| e.kind), Err(ErrorKind::InvalidStatus));
+        assert_eq!(parse_strict("_sc|Redis connection|4").map_err(|e| e.kind), Err(ErrorKind::InvalidStatus));
+        assert_eq!(parse_strict("_sc|Redis connection|0|m:timeout|h:frontend1").map_err(|e| e.kind), Err(ErrorKind::UnknownSection));
+        assert_eq!(parse_strict("_sc|Redis connection|0|").map_err(|e| e.kind), Err(ErrorKind::TrailingSeparator));
+    }
+
     #[test]
     fn test_parse_invalid() {
-        let result = parse("Redis connection".to_string());
+        let result = parse("Redis connection");
         println!("{:?}", result);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_timestamp_not_float() {
+        assert_eq!(parse("_sc|Redis connection|0|d:now").map_err(|e| e.kind), Err(ErrorKind::TimestampNotFloat));
+    }
 }
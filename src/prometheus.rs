@@ -0,0 +1,608 @@
+use std::collections::BTreeMap;
+use std::f64;
+
+use {Message, Metric, GaugeOp, DDSketch, Clock, SystemClock};
+
+/// How timings, histograms and distributions are exposed
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PrometheusTimer {
+    /// Observations are counted in buckets
+    HISTOGRAM,
+    /// Quantiles are calculated over the observations with a DDSketch,
+    /// within 1% of the actual value
+    SUMMARY
+}
+
+/// Options for the Prometheus registry
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PrometheusOptions {
+    pub timer_type: PrometheusTimer,
+    /// Upper bounds of the histogram buckets, the `+Inf` bucket is always added
+    pub buckets: Vec<f64>,
+    /// Quantiles of the summaries, between 0 and 1
+    pub quantiles: Vec<f64>,
+}
+
+impl Default for PrometheusOptions {
+    fn default() -> PrometheusOptions {
+        PrometheusOptions {
+            timer_type: PrometheusTimer::HISTOGRAM,
+            buckets: vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+            quantiles: vec![0.5, 0.9, 0.99],
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub(crate) enum FamilyType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub(crate) enum Value {
+    Counter(f64),
+    Gauge(f64),
+    Histogram { buckets: Vec<f64>, count: f64, sum: f64 },
+    /// The sketch keeps the count and sum of the observations
+    Summary(DDSketch)
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub(crate) struct Series {
+    pub(crate) value: Value,
+    /// Unix timestamp in seconds of the first message of the series
+    pub(crate) created: f64,
+}
+
+/// All series with the same name
+#[derive(Debug,Clone,PartialEq)]
+pub(crate) struct Family {
+    pub(crate) family_type: FamilyType,
+    pub(crate) help: String,
+    pub(crate) unit: Option<String>,
+    /// Series by their labels, sorted by label name
+    pub(crate) series: BTreeMap<Vec<(String, String)>, Series>,
+}
+
+/// A registry of counters, gauges, histograms and summaries that are
+/// updated with messages, for Prometheus to scrape
+#[derive(Debug,Clone,PartialEq)]
+pub struct PrometheusRegistry<C: Clock = SystemClock> {
+    pub(crate) options: PrometheusOptions,
+    clock: C,
+    pub(crate) families: BTreeMap<String, Family>,
+}
+
+impl Default for PrometheusRegistry<SystemClock> {
+    fn default() -> PrometheusRegistry<SystemClock> {
+        PrometheusRegistry::new(PrometheusOptions::default())
+    }
+}
+
+impl PrometheusRegistry<SystemClock> {
+    pub fn new(options: PrometheusOptions) -> PrometheusRegistry<SystemClock> {
+        PrometheusRegistry::with_clock(options, SystemClock)
+    }
+}
+
+impl<C: Clock> PrometheusRegistry<C> {
+    /// Creates a registry that uses the given clock for the created timestamps
+    pub fn with_clock(options: PrometheusOptions, clock: C) -> PrometheusRegistry<C> {
+        PrometheusRegistry {
+            options,
+            clock,
+            families: BTreeMap::new(),
+        }
+    }
+
+    /// Updates the registry with a message received at the current time of
+    /// the clock and returns if it was recorded, see `record_at`
+    pub fn record(&mut self, message: &Message) -> bool {
+        let now = self.clock.now();
+        self.record_at(message, now)
+    }
+
+    /// Updates the registry with a message received at the given Unix timestamp
+    /// in seconds and returns if it was recorded. The timestamp is used as the
+    /// created timestamp of new series.
+    ///
+    /// Counters and meters are counters that are scaled by their sample rate and
+    /// gauges are gauges. Timings, histograms and distributions are histograms or
+    /// summaries, timings are converted from milliseconds to seconds. Sets, service
+    /// checks, events, counters with a negative or NaN value, messages with a name that
+    /// is already used by another type and messages with samples that would have the
+    /// same name as the samples of another metric, like a counter `latency_count` and
+    /// the count of a histogram `latency`, are not recorded.
+    pub fn record_at(&mut self, message: &Message, timestamp: f64) -> bool {
+        let (family_type, values) = match message.metric {
+            Metric::Counter(ref counter) => (FamilyType::Counter, vec![counter.value]),
+            Metric::Meter(ref meter) => (FamilyType::Counter, vec![meter.value]),
+            Metric::Gauge(ref gauge) => (FamilyType::Gauge, vec![gauge.value]),
+            Metric::Timing(ref timing) => {
+                let values = timing.values.iter().map(|value| value / 1000.0).collect();
+                (self.timer_family_type(), values)
+            },
+            Metric::Histogram(ref histogram) => (self.timer_family_type(), histogram.values.clone()),
+            Metric::Distribution(ref distribution) => (self.timer_family_type(), distribution.values.clone()),
+            Metric::Set(_) | Metric::ServiceCheck(_) | Metric::Event(_) => return false
+        };
+
+        // Counters can only go up
+        if family_type == FamilyType::Counter && (values[0] < 0.0 || values[0].is_nan()) {
+            return false
+        }
+
+        let name = metric_name(&message.name);
+        let labels = labels(message);
+        let bucket_count = self.options.buckets.len();
+
+        if !self.families.contains_key(&name) && self.collides(&name, family_type) {
+            return false
+        }
+
+        let family = self.families.entry(name).or_insert_with(|| {
+            let unit = match message.metric {
+                Metric::Timing(_) => Some("seconds".to_string()),
+                _ => None
+            };
+            Family {
+                family_type,
+                help: format!("StatsD metric {}", message.name),
+                unit,
+                series: BTreeMap::new(),
+            }
+        });
+        if family.family_type != family_type {
+            return false
+        }
+
+        let series = family.series.entry(labels).or_insert_with(|| {
+            let value = match family_type {
+                FamilyType::Counter => Value::Counter(0.0),
+                FamilyType::Gauge => Value::Gauge(0.0),
+                FamilyType::Histogram => Value::Histogram { buckets: vec![0.0; bucket_count], count: 0.0, sum: 0.0 },
+                FamilyType::Summary => Value::Summary(DDSketch::default())
+            };
+            Series {
+                value,
+                created: timestamp,
+            }
+        });
+
+        let weight = message.metric.weight();
+
+        match series.value {
+            Value::Counter(ref mut total) => *total += values[0] * weight,
+            Value::Gauge(ref mut current) => {
+                if let Metric::Gauge(ref gauge) = message.metric {
+                    match gauge.op {
+                        GaugeOp::SET => *current = gauge.value,
+                        GaugeOp::INCREMENT => *current += gauge.value,
+                        GaugeOp::DECREMENT => *current -= gauge.value
+                    }
+                }
+            },
+            Value::Histogram { ref mut buckets, ref mut count, ref mut sum } => {
+                for value in values {
+                    for (bucket, upper_bound) in buckets.iter_mut().zip(self.options.buckets.iter()) {
+                        if value <= *upper_bound {
+                            *bucket += weight;
+                        }
+                    }
+                    *count += weight;
+                    *sum += value * weight;
+                }
+            },
+            Value::Summary(ref mut sketch) => {
+                for value in values {
+                    sketch.add_weighted(value, weight);
+                }
+            }
+        }
+        true
+    }
+
+    /// Sets the help text of a metric, the name is the StatsD name of the metric.
+    /// Metrics that have not been recorded yet are ignored.
+    pub fn set_help<S: Into<String>>(&mut self, name: &str, help: S) {
+        if let Some(family) = self.families.get_mut(&metric_name(name)) {
+            family.help = help.into();
+        }
+    }
+
+    /// Sets the unit of a metric, like `bytes`, the name is the StatsD name of the metric.
+    /// Timings are in `seconds`. Metrics that have not been recorded yet are ignored.
+    pub fn set_unit<S: Into<String>>(&mut self, name: &str, unit: S) {
+        if let Some(family) = self.families.get_mut(&metric_name(name)) {
+            family.unit = Some(unit.into());
+        }
+    }
+
+    /// Renders the registry in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        for (name, family) in &self.families {
+            let family_type = match family.family_type {
+                FamilyType::Counter => "counter",
+                FamilyType::Gauge => "gauge",
+                FamilyType::Histogram => "histogram",
+                FamilyType::Summary => "summary"
+            };
+            output.push_str(&format!("# HELP {} {}\n", name, escape_help(&family.help)));
+            output.push_str(&format!("# TYPE {} {}\n", name, family_type));
+
+            for (labels, series) in &family.series {
+                match series.value {
+                    Value::Counter(value) | Value::Gauge(value) => {
+                        write_sample(&mut output, name, "", labels, None, value);
+                    },
+                    Value::Histogram { ref buckets, count, sum } => {
+                        for (bucket, upper_bound) in buckets.iter().zip(self.options.buckets.iter()) {
+                            write_sample(&mut output, name, "_bucket", labels, Some(("le", format_value(*upper_bound))), *bucket);
+                        }
+                        write_sample(&mut output, name, "_bucket", labels, Some(("le", format_value(f64::INFINITY))), count);
+                        write_sample(&mut output, name, "_sum", labels, None, sum);
+                        write_sample(&mut output, name, "_count", labels, None, count);
+                    },
+                    Value::Summary(ref sketch) => {
+                        for quantile in &self.options.quantiles {
+                            write_sample(&mut output, name, "", labels, Some(("quantile", format_value(*quantile))), summary_quantile(sketch, *quantile));
+                        }
+                        write_sample(&mut output, name, "_sum", labels, None, sketch.sum());
+                        write_sample(&mut output, name, "_count", labels, None, sketch.count());
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    /// Returns if a sample of a new family has the same name as a sample of another family
+    fn collides(&self, name: &str, family_type: FamilyType) -> bool {
+        sample_names(name, family_type).iter().any(|sample| {
+            ["", "_bucket", "_sum", "_count"].iter().any(|suffix| {
+                if !sample.ends_with(suffix) {
+                    return false
+                }
+                let other = &sample[..sample.len() - suffix.len()];
+                match self.families.get(other) {
+                    Some(family) => sample_names(other, family.family_type).contains(sample),
+                    None => false
+                }
+            })
+        })
+    }
+
+    fn timer_family_type(&self) -> FamilyType {
+        match self.options.timer_type {
+            PrometheusTimer::HISTOGRAM => FamilyType::Histogram,
+            PrometheusTimer::SUMMARY => FamilyType::Summary
+        }
+    }
+}
+
+/// Maps a StatsD name to a valid Prometheus metric name, characters
+/// other than letters, digits, `_` and `:` are replaced with `_`
+pub(crate) fn metric_name(name: &str) -> String {
+    let mut metric_name: String = name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | ':' => c,
+            _ => '_'
+        })
+        .collect();
+    if metric_name.is_empty() || metric_name.starts_with(|c: char| c.is_ascii_digit()) {
+        metric_name.insert(0, '_');
+    }
+    metric_name
+}
+
+/// The names of the samples of a family in the Prometheus text format
+fn sample_names(name: &str, family_type: FamilyType) -> Vec<String> {
+    let suffixes: &[&str] = match family_type {
+        FamilyType::Counter | FamilyType::Gauge => &[""],
+        FamilyType::Histogram => &["_bucket", "_sum", "_count"],
+        FamilyType::Summary => &["", "_sum", "_count"]
+    };
+    suffixes.iter().map(|suffix| format!("{}{}", name, suffix)).collect()
+}
+
+/// Maps a tag key to a valid Prometheus label name. Labels starting with `__`
+/// are reserved and `le` and `quantile` are used by histograms and summaries,
+/// so these get a `tag` prefix.
+pub(crate) fn label_name(key: &str) -> String {
+    let mut label_name: String = key.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c,
+            _ => '_'
+        })
+        .collect();
+    if label_name.is_empty() || label_name.starts_with(|c: char| c.is_ascii_digit()) {
+        label_name.insert(0, '_');
+    }
+    if label_name.starts_with("__") {
+        label_name.insert_str(0, "tag");
+    } else if label_name == "le" || label_name == "quantile" {
+        label_name.insert_str(0, "tag_");
+    }
+    label_name
+}
+
+/// The labels for the tags of a message, sorted by name. The last value is used
+/// for names that occur more than once and labels with an empty value are left
+/// out, Prometheus treats them as missing labels.
+fn labels(message: &Message) -> Vec<(String, String)> {
+    let mut labels = BTreeMap::new();
+    if let Some(ref tags) = message.tags {
+        for tag in tags {
+            match tag.value {
+                Some(ref value) if !value.is_empty() => {
+                    labels.insert(label_name(&tag.key), value.clone());
+                },
+                _ => {
+                    labels.remove(&label_name(&tag.key));
+                }
+            }
+        }
+    }
+    labels.into_iter().collect()
+}
+
+/// The quantile of a summary, NaN when there are no observations
+pub(crate) fn summary_quantile(sketch: &DDSketch, quantile: f64) -> f64 {
+    sketch.quantile(quantile).unwrap_or(f64::NAN)
+}
+
+/// Formats a value, infinity and NaN are written as `+Inf`, `-Inf` and `NaN`
+pub(crate) fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes backslashes, double quotes and newlines in label values
+pub(crate) fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Writes a sample line with the labels and an optional extra
+/// label, like the upper bound of a bucket
+pub(crate) fn write_sample(output: &mut String, name: &str, suffix: &str, labels: &[(String, String)], extra: Option<(&str, String)>, value: f64) {
+    output.push_str(name);
+    output.push_str(suffix);
+
+    if !labels.is_empty() || extra.is_some() {
+        let mut pairs: Vec<String> = labels.iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+            .collect();
+        if let Some((name, value)) = extra {
+            pairs.push(format!("{}=\"{}\"", name, value));
+        }
+        output.push('{');
+        output.push_str(&pairs.join(","));
+        output.push('}');
+    }
+    output.push(' ');
+    output.push_str(&format_value(value));
+    output.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use parse;
+    use super::{PrometheusRegistry, PrometheusOptions, PrometheusTimer, metric_name, label_name};
+
+    fn registry(options: PrometheusOptions, lines: &[&str]) -> PrometheusRegistry {
+        let mut registry = PrometheusRegistry::new(options);
+        for line in lines {
+            registry.record(&parse(*line).unwrap());
+        }
+        registry
+    }
+
+    #[test]
+    fn test_prometheus_counter() {
+        let registry = registry(PrometheusOptions::default(), &[
+            "api.requests:1|c|#method:get",
+            "api.requests:1|c|@0.5|#method:get",
+            "api.requests:2|c|#method:post,canary",
+            "api.requests:1|m",
+        ]);
+
+        assert_eq!(registry.render(), "\
+# HELP api_requests StatsD metric api.requests
+# TYPE api_requests counter
+api_requests 1
+api_requests{method=\"get\"} 3
+api_requests{method=\"post\"} 2
+");
+    }
+
+    #[test]
+    fn test_prometheus_gauge() {
+        let mut registry = registry(PrometheusOptions::default(), &[
+            "connections:10|g",
+            "connections:+5|g",
+            "connections:-3|g",
+        ]);
+        registry.set_help("connections", "Open connections\nper host");
+
+        assert_eq!(registry.render(), "\
+# HELP connections Open connections\\nper host
+# TYPE connections gauge
+connections 12
+");
+    }
+
+    #[test]
+    fn test_prometheus_histogram() {
+        let options = PrometheusOptions { buckets: vec![0.1, 1.0], ..Default::default() };
+        let registry = registry(options, &[
+            "latency:50:500|ms",
+            "latency:2000|ms|@0.5",
+        ]);
+
+        assert_eq!(registry.render(), "\
+# HELP latency StatsD metric latency
+# TYPE latency histogram
+latency_bucket{le=\"0.1\"} 1
+latency_bucket{le=\"1\"} 2
+latency_bucket{le=\"+Inf\"} 4
+latency_sum 4.55
+latency_count 4
+");
+    }
+
+    #[test]
+    fn test_prometheus_summary() {
+        let options = PrometheusOptions { timer_type: PrometheusTimer::SUMMARY, quantiles: vec![0.5, 0.9], ..Default::default() };
+        let registry = registry(options, &[
+            "size:1:2:3:4:5:6:7:8:9:10|h|#host:web1",
+        ]);
+
+        // The quantiles are within 1% of 5 and 9
+        assert_eq!(registry.render(), "\
+# HELP size StatsD metric size
+# TYPE size summary
+size{host=\"web1\",quantile=\"0.5\"} 5.002829575110705
+size{host=\"web1\",quantile=\"0.9\"} 8.935418643763574
+size_sum{host=\"web1\"} 55
+size_count{host=\"web1\"} 10
+");
+    }
+
+    #[test]
+    fn test_prometheus_summary_sample_rate() {
+        let options = PrometheusOptions { timer_type: PrometheusTimer::SUMMARY, quantiles: vec![0.9, 1.0], ..Default::default() };
+        let registry = registry(options, &[
+            "latency:1|ms|@0.1",
+            "latency:1|ms|@0.1",
+            "latency:1|ms|@0.1",
+            "latency:1000|ms",
+        ]);
+
+        // 30 of the 31 observations are 1ms
+        assert_eq!(registry.render(), "\
+# HELP latency StatsD metric latency
+# TYPE latency summary
+latency{quantile=\"0.9\"} 0.001
+latency{quantile=\"1\"} 1
+latency_sum 1.03
+latency_count 31
+");
+    }
+
+    #[test]
+    fn test_prometheus_negative_counter() {
+        let mut registry = PrometheusRegistry::default();
+
+        // Counters can't decrease
+        assert!(registry.record(&parse("gorets:5|c").unwrap()));
+        assert!(!registry.record(&parse("gorets:-10|c").unwrap()));
+        assert!(!registry.record(&parse("errors:-1|c").unwrap()));
+
+        assert_eq!(registry.render(), "\
+# HELP gorets StatsD metric gorets
+# TYPE gorets counter
+gorets 5
+");
+    }
+
+    #[test]
+    fn test_prometheus_reserved_labels() {
+        let options = PrometheusOptions { buckets: vec![1.0], ..Default::default() };
+        let histogram = registry(options, &[
+            "latency:5|h|#le:x",
+        ]);
+
+        assert_eq!(histogram.render(), "\
+# HELP latency StatsD metric latency
+# TYPE latency histogram
+latency_bucket{tag_le=\"x\",le=\"1\"} 0
+latency_bucket{tag_le=\"x\",le=\"+Inf\"} 1
+latency_sum{tag_le=\"x\"} 5
+latency_count{tag_le=\"x\"} 1
+");
+
+        let options = PrometheusOptions { timer_type: PrometheusTimer::SUMMARY, quantiles: vec![0.5], ..Default::default() };
+        let summary = registry(options, &[
+            "size:5|h|#quantile:x",
+        ]);
+
+        assert_eq!(summary.render(), "\
+# HELP size StatsD metric size
+# TYPE size summary
+size{tag_quantile=\"x\",quantile=\"0.5\"} 5
+size_sum{tag_quantile=\"x\"} 5
+size_count{tag_quantile=\"x\"} 1
+");
+    }
+
+    #[test]
+    fn test_prometheus_name_collision() {
+        let mut registry = PrometheusRegistry::default();
+
+        // The samples of the histogram are `latency_bucket`, `latency_sum` and `latency_count`
+        assert!(registry.record(&parse("latency:5|h").unwrap()));
+        assert!(!registry.record(&parse("latency.count:5|c").unwrap()));
+        assert!(!registry.record(&parse("latency_bucket:5|g").unwrap()));
+        assert!(registry.record(&parse("latency.total:5|c").unwrap()));
+
+        let mut registry = PrometheusRegistry::default();
+        assert!(registry.record(&parse("size_sum:5|g").unwrap()));
+        assert!(!registry.record(&parse("size:5|h").unwrap()));
+    }
+
+    #[test]
+    fn test_prometheus_ignored() {
+        let mut registry = PrometheusRegistry::default();
+
+        assert!(registry.record(&parse("gorets:1|c").unwrap()));
+        assert!(!registry.record(&parse("gorets:1|g").unwrap()));
+        assert!(!registry.record(&parse("users:alice|s").unwrap()));
+        assert!(!registry.record(&parse("_sc|Redis connection|0").unwrap()));
+        assert!(!registry.record(&parse("_e{6,5}:deploy|v1|v2").unwrap()));
+    }
+
+    #[test]
+    fn test_prometheus_escaping() {
+        let registry = registry(PrometheusOptions::default(), &[
+            "gorets:1|c|#path:C:\\dir\"s\"",
+        ]);
+
+        assert_eq!(registry.render(), "\
+# HELP gorets StatsD metric gorets
+# TYPE gorets counter
+gorets{path=\"C:\\\\dir\\\"s\\\"\"} 1
+");
+    }
+
+    #[test]
+    fn test_prometheus_names() {
+        assert_eq!(metric_name("api.requests-total"), "api_requests_total");
+        assert_eq!(metric_name("5xx.errors"), "_5xx_errors");
+        assert_eq!(metric_name("node:cpu"), "node:cpu");
+        assert_eq!(label_name("redis.instance"), "redis_instance");
+        assert_eq!(label_name("node:cpu"), "node_cpu");
+        assert_eq!(label_name("__name__"), "tag__name__");
+        assert_eq!(label_name("le"), "tag_le");
+        assert_eq!(label_name("quantile"), "tag_quantile");
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
+use std::slice;
+
+/// A tag, the value is None for tags without a value (`#foo`)
+/// and empty for tags with an empty value (`#foo:`)
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tag {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+/// A tag that borrows its key and value from the input
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TagRef<'a> {
+    pub key: &'a str,
+    pub value: Option<&'a str>,
+}
+
+impl Tag {
+    fn value_or_empty(&self) -> &str {
+        match self.value {
+            Some(ref value) => value,
+            None => ""
+        }
+    }
+}
+
+impl<'a> TagRef<'a> {
+    /// Copies the borrowed key and value into an owned tag
+    pub fn to_owned(&self) -> Tag {
+        Tag {
+            key: self.key.to_owned(),
+            value: self.value.map(str::to_owned),
+        }
+    }
+}
+
+/// Tags in the order they were sent, keys can occur more than once
+#[derive(Debug,Clone,PartialEq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Tags {
+    tags: Vec<Tag>
+}
+
+impl Tags {
+    pub fn new() -> Tags {
+        Tags::default()
+    }
+
+    /// Adds a tag with a value after the existing tags
+    pub fn push<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.tags.push(Tag {
+            key: key.into(),
+            value: Some(value.into()),
+        });
+    }
+
+    /// Adds a tag without a value after the existing tags
+    pub fn push_key<K: Into<String>>(&mut self, key: K) {
+        self.tags.push(Tag {
+            key: key.into(),
+            value: None,
+        });
+    }
+
+    /// Returns the value of the first tag with the given key,
+    /// tags without a value return an empty string
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|tag| tag.key == key)
+            .map(Tag::value_or_empty)
+    }
+
+    /// Returns the values of all tags with the given key,
+    /// tags without a value return an empty string
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.tags.iter()
+            .filter(|tag| tag.key == key)
+            .map(Tag::value_or_empty)
+            .collect()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.tags.iter().any(|tag| tag.key == key)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Tag> {
+        self.tags.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Returns the tags as a map, the last value is used for keys that
+    /// occur more than once and tags without a value get an empty value
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        self.tags.iter()
+            .map(|tag| (tag.key.clone(), tag.value_or_empty().to_owned()))
+            .collect()
+    }
+}
+
+impl From<Tags> for BTreeMap<String, String> {
+    fn from(tags: Tags) -> BTreeMap<String, String> {
+        tags.tags.into_iter()
+            .map(|tag| (tag.key, tag.value.unwrap_or_default()))
+            .collect()
+    }
+}
+
+impl FromIterator<Tag> for Tags {
+    fn from_iter<I: IntoIterator<Item = Tag>>(iter: I) -> Tags {
+        Tags {
+            tags: iter.into_iter().collect()
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Tags {
+    type Item = &'a Tag;
+    type IntoIter = slice::Iter<'a, Tag>;
+
+    fn into_iter(self) -> slice::Iter<'a, Tag> {
+        self.tags.iter()
+    }
+}
+
+impl IntoIterator for Tags {
+    type Item = Tag;
+    type IntoIter = ::std::vec::IntoIter<Tag>;
+
+    fn into_iter(self) -> ::std::vec::IntoIter<Tag> {
+        self.tags.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::Tags;
+
+    fn tags() -> Tags {
+        let mut tags = Tags::new();
+        tags.push("team", "web");
+        tags.push("env", "production");
+        tags.push("team", "ops");
+        tags.push_key("canary");
+        tags
+    }
+
+    #[test]
+    fn test_get() {
+        // Returns the first value for the key
+        assert_eq!(tags().get("team"), Some("web"));
+
+        // Returns None if the key is not found
+        assert_eq!(tags().get("host"), None);
+    }
+
+    #[test]
+    fn test_get_all() {
+        // Returns all values for the key in order
+        assert_eq!(tags().get_all("team"), vec!["web", "ops"]);
+
+        // Returns nothing if the key is not found
+        assert!(tags().get_all("host").is_empty());
+    }
+
+    #[test]
+    fn test_iter() {
+        let tags = tags();
+        let keys: Vec<&str> = tags.iter().map(|tag| tag.key.as_ref()).collect();
+
+        // Keeps the order and duplicate keys
+        assert_eq!(keys, vec!["team", "env", "team", "canary"]);
+    }
+
+    #[test]
+    fn test_push_key() {
+        let tags = tags();
+        let canary = tags.iter().last().unwrap();
+
+        // Adds a tag without a value
+        assert_eq!(canary.key, "canary");
+        assert_eq!(canary.value, None);
+
+        // Looks up as an empty value
+        assert_eq!(tags.get("canary"), Some(""));
+    }
+
+    #[test]
+    fn test_to_map() {
+        let mut expected = BTreeMap::new();
+        expected.insert("env".to_string(), "production".to_string());
+        expected.insert("team".to_string(), "ops".to_string());
+        expected.insert("canary".to_string(), "".to_string());
+
+        // Uses the last value for duplicate keys
+        assert_eq!(tags().to_map(), expected);
+        assert_eq!(BTreeMap::from(tags()), expected);
+    }
+}